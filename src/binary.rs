@@ -0,0 +1,150 @@
+use std::{
+    convert::TryInto,
+    io::{self, Read},
+};
+
+use chrono::NaiveDateTime;
+
+/// Byte order of the numbers in a binary file format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ByteOrder {
+    Big,
+    Little,
+}
+
+macro_rules! read_number {
+    ($name:ident, $type:ty) => {
+        pub(crate) fn $name(self, bytes: &[u8]) -> $type {
+            let bytes = bytes[..std::mem::size_of::<$type>()]
+                .try_into()
+                .expect("Numbers are read from at least as many bytes as their type");
+            match self {
+                ByteOrder::Big => <$type>::from_be_bytes(bytes),
+                ByteOrder::Little => <$type>::from_le_bytes(bytes),
+            }
+        }
+    };
+}
+
+impl ByteOrder {
+    read_number!(u16, u16);
+    read_number!(u32, u32);
+    read_number!(u64, u64);
+    read_number!(i16, i16);
+    read_number!(i32, i32);
+    read_number!(i64, i64);
+    read_number!(f32, f32);
+    read_number!(f64, f64);
+
+    pub(crate) fn f64_bytes(self, x: f64) -> [u8; 8] {
+        match self {
+            ByteOrder::Big => x.to_be_bytes(),
+            ByteOrder::Little => x.to_le_bytes(),
+        }
+    }
+
+    /// Read an unsigned integer of up to eight bytes, as used by the parts of strL references
+    pub(crate) fn uint(self, bytes: &[u8]) -> u64 {
+        let fold = |acc: u64, byte: &u8| (acc << 8) | u64::from(*byte);
+        match self {
+            ByteOrder::Big => bytes.iter().fold(0, fold),
+            ByteOrder::Little => bytes.iter().rev().fold(0, fold),
+        }
+    }
+}
+
+pub(crate) fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Convert a count of days since a file format's epoch into days since the Unix epoch
+///
+/// Fractions of a day are discarded. Dates beyond the years that the calendar represents
+/// are invalid data, so that every date that is read can also be normalized.
+pub(crate) fn unix_days(days: f64, epoch: i32) -> io::Result<i32> {
+    let out_of_range = || invalid(format!("Date of {} days is out of range", days));
+    let days = days.floor();
+    if !(f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&days) {
+        return Err(out_of_range());
+    }
+    let days = (days as i32).checked_sub(epoch).ok_or_else(out_of_range)?;
+    match NaiveDateTime::from_timestamp_opt(i64::from(days) * 86_400, 0) {
+        Some(_) => Ok(days),
+        None => Err(out_of_range()),
+    }
+}
+
+/// Convert a count of milliseconds since a file format's epoch into milliseconds since the
+/// Unix epoch
+///
+/// Values are rounded to the nearest millisecond, and date-times beyond the years that the
+/// calendar represents are invalid data.
+pub(crate) fn unix_milliseconds(milliseconds: f64, epoch: i64) -> io::Result<i64> {
+    let out_of_range = || {
+        invalid(format!(
+            "Date-time of {} milliseconds is out of range",
+            milliseconds
+        ))
+    };
+    let rounded = milliseconds.round();
+    // i64::MAX is not representable as a double, so the upper bound is exclusive
+    if !(rounded >= i64::MIN as f64 && rounded < i64::MAX as f64) {
+        return Err(out_of_range());
+    }
+    let milliseconds = (rounded as i64)
+        .checked_sub(epoch)
+        .ok_or_else(out_of_range)?;
+    match NaiveDateTime::from_timestamp_opt(milliseconds.div_euclid(1_000), 0) {
+        Some(_) => Ok(milliseconds),
+        None => Err(out_of_range()),
+    }
+}
+
+/// Characters of Windows-1252 bytes 0x80 to 0x9F
+///
+/// Bytes that Windows-1252 leaves undefined are read as the C1 controls of Latin-1, as web
+/// browsers read them, so that every byte decodes.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Character encodings of the text in binary file formats
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Encoding {
+    Utf8,
+    /// ISO-8859-1, whose bytes are the first 256 code points
+    Latin1,
+    /// Windows-1252, which replaces most of the C1 controls of Latin-1 with printable characters
+    Windows1252,
+    /// US-ASCII, in which bytes above 0x7F are invalid
+    Ascii,
+}
+
+impl Encoding {
+    pub(crate) fn decode(self, bytes: &[u8]) -> io::Result<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| invalid(e.to_string())),
+            Encoding::Latin1 => Ok(bytes.iter().map(|x| char::from(*x)).collect()),
+            Encoding::Windows1252 => Ok(bytes
+                .iter()
+                .map(|x| match x {
+                    0x80..=0x9f => WINDOWS_1252[usize::from(x - 0x80)],
+                    _ => char::from(*x),
+                })
+                .collect()),
+            Encoding::Ascii => match bytes.iter().find(|x| !x.is_ascii()) {
+                Some(x) => Err(invalid(format!("Byte {:#04x} is not ASCII", x))),
+                None => Ok(bytes.iter().map(|x| char::from(*x)).collect()),
+            },
+        }
+    }
+}
@@ -1,16 +1,127 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::{Result, UnfError};
+
+/// Hash truncation widths, in bits, that the specification allows
+///
+/// 196 is listed by the specification alongside 192, and is accepted as an alias of 192,
+/// which is also truncated to 24 bytes, so that each digest has a single UNF string.
+pub const TRUNCATION_WIDTHS: [usize; 4] = [128, 192, 196, 256];
+
+const DEFAULT_DIGITS: u32 = 7;
+const DEFAULT_CHARACTERS: usize = 128;
+const DEFAULT_TRUNCATION: usize = 128;
+
 #[derive(Clone, Copy)]
 pub struct UnfConfigBuilder {
     digits: Option<u32>,
     characters: Option<usize>,
     truncation: Option<usize>,
     version: Option<UnfVersion>,
+    rounding: Option<Rounding>,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum UnfVersion {
+    Three,
+    Four,
+    FourOne,
+    Five,
     Six,
 }
 
+/// Rounding applied when reducing numbers to the configured significant digits
+///
+/// UNF V5 and V6 require IEEE 754 round-toward-nearest-even, while UNF V3 to V4.1 round
+/// toward zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rounding {
+    NearestEven,
+    TowardZero,
+}
+
+impl Rounding {
+    /// Rounding that the specification of a UNF version requires
+    pub fn specified(version: UnfVersion) -> Self {
+        match version {
+            UnfVersion::Three | UnfVersion::Four | UnfVersion::FourOne => Rounding::TowardZero,
+            UnfVersion::Five | UnfVersion::Six => Rounding::NearestEven,
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "nearest-even" => Ok(Rounding::NearestEven),
+            "toward-zero" => Ok(Rounding::TowardZero),
+            _ => Err(format!("Unknown rounding {}", s)),
+        }
+    }
+}
+
+/// Treatment of list elements when nested columns are flattened into leaf columns
+///
+/// Exploding hashes every element as a value of its leaf column, while serializing hashes
+/// each list as a single canonical string so that list boundaries contribute to the fingerprint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListMode {
+    Explode,
+    Serialize,
+}
+
+impl FromStr for ListMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "explode" => Ok(ListMode::Explode),
+            "serialize" => Ok(ListMode::Serialize),
+            _ => Err(format!("Unknown list mode {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rounding = match self {
+            Rounding::NearestEven => "nearest-even",
+            Rounding::TowardZero => "toward-zero",
+        };
+        write!(f, "{}", rounding)
+    }
+}
+
+impl fmt::Display for UnfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = match self {
+            UnfVersion::Three => "3",
+            UnfVersion::Four => "4",
+            UnfVersion::FourOne => "4.1",
+            UnfVersion::Five => "5",
+            UnfVersion::Six => "6",
+        };
+        write!(f, "{}", version)
+    }
+}
+
+impl FromStr for UnfVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "3" => Ok(UnfVersion::Three),
+            "4" => Ok(UnfVersion::Four),
+            "4.1" => Ok(UnfVersion::FourOne),
+            "5" => Ok(UnfVersion::Five),
+            "6" => Ok(UnfVersion::Six),
+            _ => Err(format!("Unknown UNF version {}", s)),
+        }
+    }
+}
+
 impl Default for UnfConfigBuilder {
     fn default() -> Self {
         Self::new()
@@ -24,6 +135,7 @@ impl UnfConfigBuilder {
             characters: None,
             truncation: None,
             version: None,
+            rounding: None,
         }
     }
 
@@ -47,29 +159,62 @@ impl UnfConfigBuilder {
         self
     }
 
-    pub fn build(&self) -> UnfConfig {
+    pub fn rounding(&mut self, x: Rounding) -> &mut UnfConfigBuilder {
+        self.rounding = Some(x);
+        self
+    }
+
+    /// Build the configuration, rounding as the version specifies unless told otherwise
+    ///
+    /// The parameters are not checked, so configurations that leave the crate come from
+    /// [`UnfConfigBuilder::try_build`].
+    pub(crate) fn build(&self) -> UnfConfig {
+        let version = self.version.unwrap_or(UnfVersion::Six);
         UnfConfig {
-            digits: if let Some(digits) = self.digits {
-                digits
-            } else {
-                7
-            },
-            truncation: if let Some(truncation) = self.truncation {
-                truncation
-            } else {
-                128
-            },
-            characters: if let Some(characters) = self.characters {
-                characters
-            } else {
-                128
-            },
-            version: if let Some(version) = self.version {
-                version
-            } else {
-                UnfVersion::Six
-            },
+            digits: self.digits.unwrap_or(DEFAULT_DIGITS),
+            truncation: self.truncation.unwrap_or(DEFAULT_TRUNCATION),
+            characters: self.characters.unwrap_or(DEFAULT_CHARACTERS),
+            version,
+            rounding: self
+                .rounding
+                .unwrap_or_else(|| Rounding::specified(version)),
+        }
+    }
+
+    /// Build the configuration, checking its parameters against the specification
+    ///
+    /// Digits must be between 1 and 15, at least one character must be kept, and the
+    /// truncation must be one of [`TRUNCATION_WIDTHS`], with 196 built as 192. Versions
+    /// before V6 always truncate to 128 bits.
+    pub fn try_build(&self) -> Result<UnfConfig> {
+        let mut config = self.build();
+        if !(1..=15).contains(&config.digits) {
+            return Err(UnfError::InvalidConfig(format!(
+                "digits must be between 1 and 15, not {}",
+                config.digits
+            )));
         }
+        if config.characters < 1 {
+            return Err(UnfError::InvalidConfig(
+                "characters must be at least 1".to_string(),
+            ));
+        }
+        if !TRUNCATION_WIDTHS.contains(&config.truncation) {
+            return Err(UnfError::InvalidConfig(format!(
+                "truncation must be one of 128, 192, 196 or 256, not {}",
+                config.truncation
+            )));
+        }
+        if config.truncation == 196 {
+            config.truncation = 192;
+        }
+        if !matches!(config.version, UnfVersion::Six) && config.truncation != DEFAULT_TRUNCATION {
+            return Err(UnfError::InvalidConfig(format!(
+                "truncation must be 128 before UNF V6, not {}",
+                config.truncation
+            )));
+        }
+        Ok(config)
     }
 }
 
@@ -79,4 +224,104 @@ pub struct UnfConfig {
     pub truncation: usize,
     pub characters: usize,
     pub version: UnfVersion,
+    pub rounding: Rounding,
+}
+
+impl UnfConfig {
+    /// Parameters that differ from the defaults, as written in a UNF string
+    ///
+    /// Digits are written as `N`, characters as `X` and the truncation as `H`, such as `N9,H256`.
+    /// The specification has no rounding parameter, so rounding other than the version's own
+    /// is written as the extension `RZ` or `RN` rather than passing for a canonical UNF.
+    pub fn parameters(&self) -> Vec<String> {
+        let mut parameters = Vec::new();
+        if self.digits != DEFAULT_DIGITS {
+            parameters.push(format!("N{}", self.digits));
+        }
+        if self.characters != DEFAULT_CHARACTERS {
+            parameters.push(format!("X{}", self.characters));
+        }
+        if self.truncation != DEFAULT_TRUNCATION {
+            parameters.push(format!("H{}", self.truncation));
+        }
+        if self.rounding != Rounding::specified(self.version) {
+            parameters.push(match self.rounding {
+                Rounding::TowardZero => "RZ".to_string(),
+                Rounding::NearestEven => "RN".to_string(),
+            });
+        }
+        parameters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(UnfConfigBuilder::new().try_build().is_ok());
+        for truncation in TRUNCATION_WIDTHS {
+            assert!(UnfConfigBuilder::new()
+                .truncation(truncation)
+                .try_build()
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn parameters() {
+        assert!(UnfConfigBuilder::new().build().parameters().is_empty());
+        assert_eq!(
+            UnfConfigBuilder::new()
+                .digits(9)
+                .characters(256)
+                .truncation(256)
+                .build()
+                .parameters(),
+            vec!["N9", "X256", "H256"]
+        );
+        assert_eq!(
+            UnfConfigBuilder::new()
+                .rounding(Rounding::TowardZero)
+                .build()
+                .parameters(),
+            vec!["RZ"]
+        );
+        let three = UnfConfigBuilder::new().version(UnfVersion::Three).build();
+        assert_eq!(three.rounding, Rounding::TowardZero);
+        assert!(three.parameters().is_empty());
+        assert_eq!(
+            UnfConfigBuilder::new()
+                .version(UnfVersion::Three)
+                .rounding(Rounding::NearestEven)
+                .build()
+                .parameters(),
+            vec!["RN"]
+        );
+    }
+
+    #[test]
+    fn truncation_alias() {
+        let config = UnfConfigBuilder::new().truncation(196).try_build().unwrap();
+        assert_eq!(config.truncation, 192);
+        assert_eq!(config.parameters(), vec!["H192"]);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let invalid = [
+            UnfConfigBuilder::new().digits(0).try_build(),
+            UnfConfigBuilder::new().digits(16).try_build(),
+            UnfConfigBuilder::new().characters(0).try_build(),
+            UnfConfigBuilder::new().truncation(100).try_build(),
+            UnfConfigBuilder::new()
+                .truncation(256)
+                .version(UnfVersion::Five)
+                .try_build(),
+        ];
+        for config in invalid {
+            assert!(matches!(config, Err(UnfError::InvalidConfig(_))));
+        }
+    }
 }
@@ -0,0 +1,77 @@
+use std::{error::Error, fmt, io};
+
+use arrow::{datatypes::DataType, error::ArrowError};
+
+/// Errors that can occur while reading data or calculating a UNF
+#[derive(Debug)]
+pub enum UnfError {
+    /// The column type has no UNF normalization
+    UnsupportedType(DataType),
+    /// IPC buffers compressed with a codec, which the Arrow reader does not decode
+    UnsupportedCompression(String),
+    Io(io::Error),
+    Arrow(ArrowError),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    /// The data does not match the schema the hash was built for
+    SchemaMismatch(String),
+    InvalidConfig(String),
+    /// A value that has no normalization, such as a date beyond the supported years
+    OutOfRange(String),
+    /// A UNF string that could not be parsed
+    InvalidUnf(String),
+}
+
+pub type Result<T> = std::result::Result<T, UnfError>;
+
+impl fmt::Display for UnfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnfError::UnsupportedType(data_type) => {
+                write!(f, "Unsupported column type {:?}", data_type)
+            }
+            UnfError::UnsupportedCompression(codec) => {
+                write!(f, "Unsupported IPC compression {}", codec)
+            }
+            UnfError::Io(e) => write!(f, "I/O error: {}", e),
+            UnfError::Arrow(e) => write!(f, "Arrow error: {}", e),
+            #[cfg(feature = "parquet")]
+            UnfError::Parquet(e) => write!(f, "Parquet error: {}", e),
+            UnfError::SchemaMismatch(message) => write!(f, "Schema mismatch: {}", message),
+            UnfError::InvalidConfig(message) => write!(f, "Invalid configuration: {}", message),
+            UnfError::OutOfRange(message) => write!(f, "Value out of range: {}", message),
+            UnfError::InvalidUnf(message) => write!(f, "Invalid UNF: {}", message),
+        }
+    }
+}
+
+impl Error for UnfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UnfError::Io(e) => Some(e),
+            UnfError::Arrow(e) => Some(e),
+            #[cfg(feature = "parquet")]
+            UnfError::Parquet(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for UnfError {
+    fn from(e: io::Error) -> Self {
+        UnfError::Io(e)
+    }
+}
+
+impl From<ArrowError> for UnfError {
+    fn from(e: ArrowError) -> Self {
+        UnfError::Arrow(e)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for UnfError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        UnfError::Parquet(e)
+    }
+}
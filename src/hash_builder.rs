@@ -1,18 +1,25 @@
-use std::sync::Arc;
+use std::{fmt, str::FromStr, sync::Arc};
 
 use crate::{
-    config::{UnfConfig, UnfVersion},
-    unf_vector::UNFVector,
+    config::{ListMode, Rounding, UnfConfig, UnfConfigBuilder, UnfVersion},
+    error::{Result, UnfError},
+    nested::{is_nested, leaf_schema, serialized_raw, unf_leaf_batch},
+    unf_vector::{Float16Values, UNFVector},
 };
 use arrow::{
     array::{
-        Float32Array, Float64Array, Int32Array, Int64Array, StringArray, UInt16Array, UInt32Array,
-        UInt64Array,
+        ArrayRef, BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray,
+        FixedSizeBinaryArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array,
+        Int8Array, LargeBinaryArray, LargeStringArray, NullArray, StringArray,
+        Time32MillisecondArray, Time32SecondArray, Time64MicrosecondArray, Time64NanosecondArray,
+        TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
+        TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
     },
-    datatypes::Schema,
+    compute::cast,
+    datatypes::{DataType, Schema, TimeUnit},
     record_batch::RecordBatch,
 };
-use base64::encode;
+use base64::{decode, encode};
 use md5::Md5;
 use sha2::{Digest, Sha256};
 
@@ -22,185 +29,584 @@ enum UnfHashers {
     ThreeMinus(Vec<Md5>),
 }
 
+impl UnfHashers {
+    fn new(version: UnfVersion, column_count: usize) -> Self {
+        match version {
+            UnfVersion::Three => {
+                UnfHashers::ThreeMinus((0..column_count).map(|_| Md5::new()).collect())
+            }
+            UnfVersion::Four | UnfVersion::FourOne | UnfVersion::Five | UnfVersion::Six => {
+                UnfHashers::FourPlus((0..column_count).map(|_| Sha256::new()).collect())
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UnfHash {
     pub short_hash: String,
+    /// Digest truncated to the configured width, which is what `short_hash` encodes
     pub hash: Vec<u8>,
-    pub version: UnfVersion,
+    /// Complete digest, which is only known when the UNF was computed rather than parsed
+    pub digest: Option<Vec<u8>>,
+    pub config: UnfConfig,
+}
+
+impl UnfHash {
+    /// Canonical UNF string, such as `UNF:6:<base64>` or `UNF:6:N9,H256:<base64>`
+    pub fn to_unf_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for UnfHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parameters = self.config.parameters();
+        if parameters.is_empty() {
+            write!(f, "UNF:{}:{}", self.config.version, self.short_hash)
+        } else {
+            write!(
+                f,
+                "UNF:{}:{}:{}",
+                self.config.version,
+                parameters.join(","),
+                self.short_hash
+            )
+        }
+    }
+}
+
+/// Parse a UNF string back into its version, configuration and hash
+///
+/// Parameters that are absent take their default values, and `RZ` or `RN` select rounding
+/// toward zero or to nearest even in place of the version's own rounding.
+impl FromStr for UnfHash {
+    type Err = UnfError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |message: &str| UnfError::InvalidUnf(format!("{} in '{}'", message, s));
+        let parts = s.trim().split(':').collect::<Vec<&str>>();
+        let (version, parameters, short_hash) = match parts.as_slice() {
+            ["UNF", version, short_hash] => (version, None, short_hash),
+            ["UNF", version, parameters, short_hash] => (version, Some(parameters), short_hash),
+            _ => return Err(invalid("Expected UNF:<version>:[<parameters>:]<hash>")),
+        };
+        let version: UnfVersion = version.parse().map_err(|_| invalid("Unknown version"))?;
+        let mut builder = UnfConfigBuilder::new();
+        builder.version(version);
+        for parameter in parameters.iter().flat_map(|x| x.split(',')) {
+            let unknown = || invalid(&format!("Unknown parameter {}", parameter));
+            let mut characters = parameter.chars();
+            let name = characters.next();
+            let value = characters.as_str();
+            match name {
+                Some('N') => builder.digits(value.parse().map_err(|_| unknown())?),
+                Some('X') => builder.characters(value.parse().map_err(|_| unknown())?),
+                Some('H') => builder.truncation(value.parse().map_err(|_| unknown())?),
+                Some('R') if value == "Z" => builder.rounding(Rounding::TowardZero),
+                Some('R') if value == "N" => builder.rounding(Rounding::NearestEven),
+                _ => return Err(unknown()),
+            };
+        }
+        let config = builder.try_build()?;
+        let hash = decode(short_hash).map_err(|_| invalid("Hash is not base64"))?;
+        let digest_length = match version {
+            UnfVersion::Three => 16,
+            UnfVersion::Four | UnfVersion::FourOne | UnfVersion::Five | UnfVersion::Six => 32,
+        };
+        if hash.len() != digest_length.min(config.truncation / 8) {
+            return Err(invalid("Hash length does not match the truncation"));
+        }
+        Ok(UnfHash {
+            short_hash: short_hash.to_string(),
+            hash,
+            digest: None,
+            config,
+        })
+    }
+}
+
+/// UNF of a dataset along with the UNF of each of its columns
+#[derive(Debug)]
+pub struct DatasetUnf {
+    pub unf: UnfHash,
+    pub columns: Vec<ColumnUnf>,
+    pub rows: usize,
+}
+
+/// UNF of a single column, identified by its schema field
+#[derive(Debug)]
+pub struct ColumnUnf {
+    pub name: String,
+    pub data_type: DataType,
+    pub unf: UnfHash,
 }
 
 pub struct UnfHashBuilder {
     schema: Arc<Schema>,
-    version: UnfVersion,
     hash: UnfHashers,
     config: UnfConfig,
+    list_mode: Option<ListMode>,
 }
 
 impl UnfHashBuilder {
-    pub fn new(schema: Arc<Schema>, version: UnfVersion, config: UnfConfig) -> Self {
-        let hash = match version {
-            UnfVersion::Six => UnfHashers::FourPlus(
-                (0..schema.fields().len())
-                    .into_iter()
-                    .map(|_| Sha256::new())
-                    .collect::<Vec<Sha256>>(),
-            ),
-        };
+    pub fn new(schema: Arc<Schema>, config: UnfConfig) -> Self {
+        let hash = UnfHashers::new(config.version, schema.fields().len());
         UnfHashBuilder {
             schema,
-            version,
             hash,
             config,
+            list_mode: None,
         }
     }
 
-    pub(crate) fn hash(&mut self, batch: RecordBatch) -> &Self {
-        match self.version {
-            UnfVersion::Six => match self.hash {
+    /// Flatten nested columns into leaf columns, each with its own hash
+    ///
+    /// Struct fields become dotted leaf columns, and list elements are either exploded
+    /// into the leaves of their items or serialized canonically as a single value.
+    pub fn flatten_nested(mut self, list_mode: ListMode) -> Self {
+        let leaf_count = leaf_schema(&self.schema, list_mode).fields().len();
+        self.hash = UnfHashers::new(self.config.version, leaf_count);
+        self.list_mode = Some(list_mode);
+        self
+    }
+
+    /// Columns that are hashed, which are the leaf columns when nested columns are flattened
+    pub fn column_schema(&self) -> Arc<Schema> {
+        match self.list_mode {
+            Some(list_mode) => Arc::new(leaf_schema(&self.schema, list_mode)),
+            None => self.schema.clone(),
+        }
+    }
+
+    pub(crate) fn hash(&mut self, batch: RecordBatch) -> Result<&Self> {
+        if let Some(list_mode) = self.list_mode {
+            match self.hash {
                 UnfHashers::FourPlus(ref mut hashers) => {
-                    unf_batch(batch, &self.schema, self.config, hashers);
+                    unf_leaf_batch(batch, &self.schema, self.config, list_mode, hashers)?;
+                }
+                UnfHashers::ThreeMinus(ref mut hashers) => {
+                    unf_leaf_batch(batch, &self.schema, self.config, list_mode, hashers)?;
                 }
-                UnfHashers::ThreeMinus(_) => unreachable!(),
-            },
+            };
+            return Ok(self);
+        }
+        match self.hash {
+            UnfHashers::FourPlus(ref mut hashers) => {
+                unf_batch(batch, &self.schema, self.config, hashers)?;
+            }
+            UnfHashers::ThreeMinus(ref mut hashers) => {
+                unf_batch(batch, &self.schema, self.config, hashers)?;
+            }
         };
-        self
+        Ok(self)
     }
 
-    pub(crate) fn finalize(self) -> Vec<UnfHash> {
-        let truncation = self.config.truncation / 8;
-        let version = self.version;
-        match self.hash {
-            UnfHashers::FourPlus(hash) => hash
-                .into_iter()
-                .map(|x| {
-                    let output: Vec<u8> = x.finalize().to_vec();
-                    let short_hash = encode(
-                        output
-                            .clone()
-                            .into_iter()
-                            .take(truncation)
-                            .collect::<Vec<u8>>(),
-                    );
-                    UnfHash {
-                        short_hash,
-                        hash: output,
-                        version,
-                    }
-                })
-                .collect(),
-            UnfHashers::ThreeMinus(_) => todo!(),
-        }
+    /// Configuration that every column, and the dataset, is hashed with
+    pub fn config(&self) -> UnfConfig {
+        self.config
     }
-}
 
-/// Create a UNF Hash from a single Record Batch
-pub fn unf_from_batch(input: RecordBatch, schema: &Arc<Schema>, config: UnfConfig) -> UnfHash {
-    let mut hasher: Vec<Sha256> = vec![Sha256::new()];
-
-    unf_batch(input, schema, config, &mut hasher);
-    let hash = hasher.pop().unwrap().finalize().to_vec();
-    let short_hash = encode(
-        hash.clone()
-            .into_iter()
-            .take(config.truncation / 8)
-            .collect::<Vec<u8>>(),
-    );
+    pub(crate) fn finalize(self) -> Result<Vec<UnfHash>> {
+        let config = self.config;
+        Ok(match self.hash {
+            UnfHashers::FourPlus(hash) => hash.into_iter().map(|x| unf_hash(x, config)).collect(),
+            UnfHashers::ThreeMinus(hash) => hash.into_iter().map(|x| unf_hash(x, config)).collect(),
+        })
+    }
+}
 
+/// Finalize a single hasher into a UNF Hash
+///
+/// UNF V3 base64 encodes the complete 128 bit MD5 digest, so any truncation
+/// beyond the digest length has no effect.
+fn unf_hash<D: Digest>(hasher: D, config: UnfConfig) -> UnfHash {
+    let digest = hasher.finalize().to_vec();
+    let hash = digest
+        .iter()
+        .take(config.truncation / 8)
+        .copied()
+        .collect::<Vec<u8>>();
     UnfHash {
-        short_hash,
+        short_hash: encode(&hash),
         hash,
-        version: config.version,
+        digest: Some(digest),
+        config,
+    }
+}
+
+/// Create a UNF Hash from a single Record Batch
+pub fn unf_from_batch(
+    input: RecordBatch,
+    schema: &Arc<Schema>,
+    config: UnfConfig,
+) -> Result<UnfHash> {
+    match config.version {
+        UnfVersion::Three => {
+            let mut hasher = [Md5::new()];
+            unf_batch(input, schema, config, &mut hasher)?;
+            let [hasher] = hasher;
+            Ok(unf_hash(hasher, config))
+        }
+        UnfVersion::Four | UnfVersion::FourOne | UnfVersion::Five | UnfVersion::Six => {
+            let mut hasher = [Sha256::new()];
+            unf_batch(input, schema, config, &mut hasher)?;
+            let [hasher] = hasher;
+            Ok(unf_hash(hasher, config))
+        }
     }
 }
 
-/// Update SHA256 Hashes for a given RecordBatch
+/// Update column Hashes for a given RecordBatch
 ///
-/// Suitable for UNF V4 and above, but not suitable for V3 which relies upon md5.
+/// Generic over the digest so that it serves both UNF V3, which relies upon md5,
+/// and UNF V4 and above, which rely upon SHA256.
 /// Assumes that the ordering of the hashes matches the ordering of the schemas.
-pub(crate) fn unf_batch(
+pub(crate) fn unf_batch<D: Digest>(
     input: RecordBatch,
     schema: &Arc<Schema>,
     config: UnfConfig,
-    hash: &mut Vec<Sha256>,
-) {
+    hash: &mut [D],
+) -> Result<()> {
+    check_schema(&input, schema)?;
     for (column_index, column) in input.columns().iter().enumerate() {
-        let col = column.as_any();
-        let raw_column_data = match schema.field(column_index).data_type() {
-            arrow::datatypes::DataType::Null => todo!(),
-            arrow::datatypes::DataType::Boolean => todo!(),
-            arrow::datatypes::DataType::Int8 => col
-                .downcast_ref::<Int32Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Int16 => col
-                .downcast_ref::<Int32Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Int32 => col
-                .downcast_ref::<Int32Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Int64 => col
-                .downcast_ref::<Int64Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::UInt8 => col
-                .downcast_ref::<UInt16Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::UInt16 => col
-                .downcast_ref::<UInt16Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::UInt32 => col
-                .downcast_ref::<UInt32Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::UInt64 => col
-                .downcast_ref::<UInt64Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Float16 => col
-                .downcast_ref::<Float32Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Float32 => col
-                .downcast_ref::<Float32Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Float64 => col
-                .downcast_ref::<Float64Array>()
-                .expect("Failed to Downcast")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::Timestamp(_, _) => todo!(),
-            arrow::datatypes::DataType::Date32 => todo!(),
-            arrow::datatypes::DataType::Date64 => todo!(),
-            arrow::datatypes::DataType::Time32(_) => todo!(),
-            arrow::datatypes::DataType::Time64(_) => todo!(),
-            arrow::datatypes::DataType::Duration(_) => todo!(),
-            arrow::datatypes::DataType::Interval(_) => todo!(),
-            arrow::datatypes::DataType::Binary => todo!(),
-            arrow::datatypes::DataType::FixedSizeBinary(_) => todo!(),
-            arrow::datatypes::DataType::LargeBinary => todo!(),
-            arrow::datatypes::DataType::Utf8 => col
-                .downcast_ref::<StringArray>()
-                .expect("Failed to downcast Utf8 -> StringArray")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::LargeUtf8 => col
-                .downcast_ref::<StringArray>()
-                .expect("Failed to downcast Utf8 -> StringArray")
-                .raw(config.characters, config.digits),
-            arrow::datatypes::DataType::List(_) => todo!(),
-            arrow::datatypes::DataType::FixedSizeList(_, _) => todo!(),
-            arrow::datatypes::DataType::LargeList(_) => todo!(),
-            arrow::datatypes::DataType::Struct(_) => todo!(),
-            arrow::datatypes::DataType::Union(_) => todo!(),
-            arrow::datatypes::DataType::Dictionary(_, _) => todo!(),
-            arrow::datatypes::DataType::Decimal(_, _) => todo!(),
-        };
+        let raw_column_data = column_raw(column, schema.field(column_index).data_type(), &config)?;
         let hasher = &mut hash[column_index];
         for x in raw_column_data {
             hasher.update(x)
         }
     }
+    Ok(())
+}
+
+/// Check that a RecordBatch has the columns of the schema its hashes were built for
+pub(crate) fn check_schema(input: &RecordBatch, schema: &Schema) -> Result<()> {
+    if input.num_columns() != schema.fields().len() {
+        return Err(UnfError::SchemaMismatch(format!(
+            "Expected {} columns but the batch has {}",
+            schema.fields().len(),
+            input.num_columns()
+        )));
+    }
+    for (column, field) in input.columns().iter().zip(schema.fields()) {
+        if column.data_type() != field.data_type() {
+            return Err(UnfError::SchemaMismatch(format!(
+                "Column {} is {:?} but the batch has {:?}",
+                field.name(),
+                field.data_type(),
+                column.data_type()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Normalize and encode every value of a single column
+pub(crate) fn column_raw(
+    column: &ArrayRef,
+    data_type: &DataType,
+    config: &UnfConfig,
+) -> Result<Vec<Vec<u8>>> {
+    match data_type {
+        // Nested columns that are not flattened are hashed by their canonical serialization
+        _ if is_nested(data_type) => serialized_raw(column, data_type, config),
+        // Dictionaries are hashed by their decoded values, so a factor fingerprints the same
+        // as the equivalent plain column
+        arrow::datatypes::DataType::Dictionary(_, value_type) => {
+            column_raw(&cast(column, value_type)?, value_type, config)
+        }
+        _ => unf_vector(column, data_type)?.raw(config),
+    }
+}
+
+/// Downcast a column to the normalization for its data type
+pub(crate) fn unf_vector<'a>(
+    column: &'a ArrayRef,
+    data_type: &DataType,
+) -> Result<Box<dyn UNFVector + 'a>> {
+    Ok(match data_type {
+        arrow::datatypes::DataType::Null => Box::new(downcast::<NullArray>(column)?),
+        arrow::datatypes::DataType::Boolean => Box::new(downcast::<BooleanArray>(column)?),
+        arrow::datatypes::DataType::Int8 => Box::new(downcast::<Int8Array>(column)?),
+        arrow::datatypes::DataType::Int16 => Box::new(downcast::<Int16Array>(column)?),
+        arrow::datatypes::DataType::Int32 => Box::new(downcast::<Int32Array>(column)?),
+        arrow::datatypes::DataType::Int64 => Box::new(downcast::<Int64Array>(column)?),
+        arrow::datatypes::DataType::UInt8 => Box::new(downcast::<UInt8Array>(column)?),
+        arrow::datatypes::DataType::UInt16 => Box::new(downcast::<UInt16Array>(column)?),
+        arrow::datatypes::DataType::UInt32 => Box::new(downcast::<UInt32Array>(column)?),
+        arrow::datatypes::DataType::UInt64 => Box::new(downcast::<UInt64Array>(column)?),
+        arrow::datatypes::DataType::Float16 => Box::new(Float16Values(column.data())),
+        arrow::datatypes::DataType::Float32 => Box::new(downcast::<Float32Array>(column)?),
+        arrow::datatypes::DataType::Float64 => Box::new(downcast::<Float64Array>(column)?),
+        arrow::datatypes::DataType::Timestamp(TimeUnit::Second, _) => {
+            Box::new(downcast::<TimestampSecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            Box::new(downcast::<TimestampMillisecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            Box::new(downcast::<TimestampMicrosecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            Box::new(downcast::<TimestampNanosecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Date32 => Box::new(downcast::<Date32Array>(column)?),
+        arrow::datatypes::DataType::Date64 => Box::new(downcast::<Date64Array>(column)?),
+        arrow::datatypes::DataType::Time32(TimeUnit::Second) => {
+            Box::new(downcast::<Time32SecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Time32(TimeUnit::Millisecond) => {
+            Box::new(downcast::<Time32MillisecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Time64(TimeUnit::Microsecond) => {
+            Box::new(downcast::<Time64MicrosecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Time64(TimeUnit::Nanosecond) => {
+            Box::new(downcast::<Time64NanosecondArray>(column)?)
+        }
+        arrow::datatypes::DataType::Binary => Box::new(downcast::<BinaryArray>(column)?),
+        arrow::datatypes::DataType::FixedSizeBinary(_) => {
+            Box::new(downcast::<FixedSizeBinaryArray>(column)?)
+        }
+        arrow::datatypes::DataType::LargeBinary => Box::new(downcast::<LargeBinaryArray>(column)?),
+        arrow::datatypes::DataType::Utf8 => Box::new(downcast::<StringArray>(column)?),
+        arrow::datatypes::DataType::LargeUtf8 => Box::new(downcast::<LargeStringArray>(column)?),
+        // Nested columns are flattened or serialized, and dictionaries are decoded, before
+        // normalization, so these are the types without a normalization of their own
+        arrow::datatypes::DataType::Time32(_)
+        | arrow::datatypes::DataType::Time64(_)
+        | arrow::datatypes::DataType::Duration(_)
+        | arrow::datatypes::DataType::Interval(_)
+        | arrow::datatypes::DataType::List(_)
+        | arrow::datatypes::DataType::FixedSizeList(_, _)
+        | arrow::datatypes::DataType::LargeList(_)
+        | arrow::datatypes::DataType::Struct(_)
+        | arrow::datatypes::DataType::Union(_)
+        | arrow::datatypes::DataType::Map(_, _)
+        | arrow::datatypes::DataType::Dictionary(_, _) => {
+            return Err(UnfError::UnsupportedType(data_type.clone()))
+        }
+        arrow::datatypes::DataType::Decimal(_, _) => Box::new(downcast::<DecimalArray>(column)?),
+    })
+}
+
+/// Downcast a column to the array type of its declared data type
+pub(crate) fn downcast<T: 'static>(column: &ArrayRef) -> Result<&T> {
+    column.as_any().downcast_ref::<T>().ok_or_else(|| {
+        UnfError::SchemaMismatch(format!(
+            "Column of type {:?} does not match its declared type",
+            column.data_type()
+        ))
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::UnfConfigBuilder;
+    use arrow::{
+        array::{DictionaryArray, Int32Array, PrimitiveBuilder, PrimitiveDictionaryBuilder},
+        datatypes::{Int16Type, Int32Type, Int8Type, UInt32Type},
+    };
+
+    /// Short hash of a single column
+    pub(crate) fn column_hash(column: ArrayRef) -> String {
+        let data_type = column.data_type().clone();
+        let schema = Arc::new(Schema::new(vec![arrow::datatypes::Field::new(
+            "Column", data_type, true,
+        )]));
+        let config = UnfConfigBuilder::new().build();
+        let batch = RecordBatch::try_new(schema.clone(), vec![column]).unwrap();
+        unf_from_batch(batch, &schema, config).unwrap().short_hash
+    }
+
+    #[test]
+    fn string_encodings_match() {
+        let values = vec![Some("low"), None, Some("high"), Some("low")];
+        let plain = column_hash(Arc::new(StringArray::from(values.clone())));
+        assert_eq!(
+            column_hash(Arc::new(LargeStringArray::from(values.clone()))),
+            plain
+        );
+        assert_eq!(
+            column_hash(Arc::new(
+                values
+                    .clone()
+                    .into_iter()
+                    .collect::<DictionaryArray<Int8Type>>()
+            )),
+            plain
+        );
+        assert_eq!(
+            column_hash(Arc::new(
+                values
+                    .clone()
+                    .into_iter()
+                    .collect::<DictionaryArray<Int16Type>>()
+            )),
+            plain
+        );
+        assert_eq!(
+            column_hash(Arc::new(
+                values.into_iter().collect::<DictionaryArray<UInt32Type>>()
+            )),
+            plain
+        );
+    }
+
+    #[test]
+    fn integer_dictionary_matches_values() {
+        let mut builder = PrimitiveDictionaryBuilder::<Int8Type, Int32Type>::new(
+            PrimitiveBuilder::new(4),
+            PrimitiveBuilder::new(2),
+        );
+        builder.append(20).unwrap();
+        builder.append(10).unwrap();
+        builder.append_null().unwrap();
+        builder.append(20).unwrap();
+        assert_eq!(
+            column_hash(Arc::new(builder.finish())),
+            column_hash(Arc::new(Int32Array::from(vec![
+                Some(20),
+                Some(10),
+                None,
+                Some(20)
+            ])))
+        );
+    }
+
+    #[test]
+    fn decimal_column() {
+        let mut builder = arrow::array::DecimalBuilder::new(2, 5, 2);
+        builder.append_value(150).unwrap();
+        builder.append_null().unwrap();
+        assert_eq!(
+            column_hash(Arc::new(builder.finish())),
+            column_hash(Arc::new(Float64Array::from(vec![Some(1.5), None])))
+        );
+    }
+
+    #[test]
+    fn unsupported_type() {
+        let column: ArrayRef = Arc::new(arrow::array::DurationSecondArray::from(vec![1]));
+        let schema = Arc::new(Schema::new(vec![arrow::datatypes::Field::new(
+            "Column",
+            column.data_type().clone(),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![column]).unwrap();
+        let config = UnfConfigBuilder::new().build();
+        assert!(matches!(
+            unf_from_batch(batch, &schema, config),
+            Err(UnfError::UnsupportedType(DataType::Duration(
+                TimeUnit::Second
+            )))
+        ));
+    }
+
+    #[test]
+    fn schema_mismatch() {
+        let schema = Arc::new(Schema::new(vec![arrow::datatypes::Field::new(
+            "Column",
+            DataType::Int32,
+            true,
+        )]));
+        let other = Arc::new(Schema::new(vec![arrow::datatypes::Field::new(
+            "Column",
+            DataType::Utf8,
+            true,
+        )]));
+        let batch =
+            RecordBatch::try_new(other, vec![Arc::new(StringArray::from(vec!["a"]))]).unwrap();
+        let config = UnfConfigBuilder::new().build();
+        let mut builder = UnfHashBuilder::new(schema, config);
+        assert!(matches!(
+            builder.hash(batch),
+            Err(UnfError::SchemaMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn unf_strings() {
+        let column: ArrayRef = Arc::new(Int32Array::from(vec![1, 2]));
+        let schema = Arc::new(Schema::new(vec![arrow::datatypes::Field::new(
+            "Column",
+            DataType::Int32,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![column]).unwrap();
+        let default =
+            unf_from_batch(batch.clone(), &schema, UnfConfigBuilder::new().build()).unwrap();
+        assert_eq!(
+            default.to_unf_string(),
+            format!("UNF:6:{}", default.short_hash)
+        );
+        let config = UnfConfigBuilder::new().digits(9).truncation(256).build();
+        let wide = unf_from_batch(batch.clone(), &schema, config).unwrap();
+        let unf_string = wide.to_string();
+        assert_eq!(unf_string, format!("UNF:6:N9,H256:{}", wide.short_hash));
+
+        let parsed: UnfHash = unf_string.parse().unwrap();
+        assert!(matches!(parsed.config.version, UnfVersion::Six));
+        assert_eq!(parsed.config.digits, 9);
+        assert_eq!(parsed.config.characters, 128);
+        assert_eq!(parsed.config.truncation, 256);
+        assert_eq!(parsed.hash, wide.hash);
+        assert_eq!(parsed.to_string(), unf_string);
+
+        assert_eq!(default.hash.len(), 16);
+        assert_eq!(default.digest.as_ref().unwrap().len(), 32);
+        assert!(default.digest.as_ref().unwrap().starts_with(&default.hash));
+        let parsed: UnfHash = default.to_string().parse().unwrap();
+        assert_eq!(parsed.hash, default.hash);
+        assert!(parsed.digest.is_none());
+
+        let config = UnfConfigBuilder::new().truncation(196).try_build().unwrap();
+        let alias = unf_from_batch(batch.clone(), &schema, config).unwrap();
+        assert_eq!(
+            alias.to_string(),
+            format!("UNF:6:H192:{}", alias.short_hash)
+        );
+        let parsed: UnfHash = format!("UNF:6:H196:{}", alias.short_hash).parse().unwrap();
+        assert_eq!(parsed.to_string(), alias.to_string());
+
+        let config = UnfConfigBuilder::new()
+            .rounding(Rounding::TowardZero)
+            .build();
+        let truncated = unf_from_batch(batch, &schema, config).unwrap();
+        let unf_string = truncated.to_string();
+        assert_eq!(unf_string, format!("UNF:6:RZ:{}", truncated.short_hash));
+        let parsed: UnfHash = unf_string.parse().unwrap();
+        assert_eq!(parsed.config.rounding, Rounding::TowardZero);
+        assert_eq!(parsed.to_string(), unf_string);
+    }
+
+    #[test]
+    fn invalid_unf_strings() {
+        for unf_string in [
+            "UNF:6",
+            "UNS:6:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:7:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:6:Q9:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:6:Né:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:6:H256:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:6:RX:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:6:not base64",
+        ] {
+            assert!(
+                matches!(unf_string.parse::<UnfHash>(), Err(UnfError::InvalidUnf(_))),
+                "{}",
+                unf_string
+            );
+        }
+        for unf_string in [
+            "UNF:6:N0:Isf0CgUVrEZzLZdf5G46TA==",
+            "UNF:5:H256:Isf0CgUVrEZzLZdf5G46TA==",
+        ] {
+            assert!(matches!(
+                unf_string.parse::<UnfHash>(),
+                Err(UnfError::InvalidConfig(_))
+            ));
+        }
+    }
 }
@@ -3,49 +3,84 @@ use std::sync::Arc;
 use arrow::{
     array::StringArray,
     datatypes::{DataType, Field, Schema},
+    error::Result as ArrowResult,
     record_batch::RecordBatch,
 };
 
-use hash_builder::{unf_from_batch, UnfHashBuilder};
+use error::Result;
+use hash_builder::{unf_from_batch, ColumnUnf, DatasetUnf, UnfHash, UnfHashBuilder};
 
+mod binary;
 pub mod config;
+pub mod error;
 pub mod hash_builder;
+mod nested;
+mod numeric;
+mod spss;
+mod stata;
 mod unf_vector;
 pub mod utils;
 
 /// Calculate a UNF Hash for a given set of Records
-pub fn calculate_unf<I>(
-    mut unf_hash: UnfHashBuilder,
-    batch_input: I,
-    config: config::UnfConfig,
-) -> hash_builder::UnfHash
+///
+/// Reading stops at the first batch that fails to be read, so that a partially read
+/// file never produces a fingerprint.
+pub fn calculate_unf<I>(unf_hash: UnfHashBuilder, batch_input: I) -> Result<UnfHash>
 where
-    I: Iterator<Item = RecordBatch>,
+    I: Iterator<Item = ArrowResult<RecordBatch>>,
 {
+    Ok(calculate_dataset_unf(unf_hash, batch_input)?.unf)
+}
+
+/// Calculate the UNF Hash of a given set of Records along with the UNF Hash of each column
+pub fn calculate_dataset_unf<I>(mut unf_hash: UnfHashBuilder, batch_input: I) -> Result<DatasetUnf>
+where
+    I: Iterator<Item = ArrowResult<RecordBatch>>,
+{
+    let mut rows = 0;
     for batch in batch_input {
-        unf_hash.hash(batch);
+        let batch = batch?;
+        rows += batch.num_rows();
+        unf_hash.hash(batch)?;
     }
+    let config = unf_hash.config();
+    let column_schema = unf_hash.column_schema();
+    let columns = column_schema
+        .fields()
+        .iter()
+        .zip(unf_hash.finalize()?)
+        .map(|(field, unf)| ColumnUnf {
+            name: field.name().clone(),
+            data_type: field.data_type().clone(),
+            unf,
+        })
+        .collect::<Vec<ColumnUnf>>();
     let schema = Arc::new(Schema::new(vec![Field::new(
         "ColumnHashes",
         DataType::Utf8,
         false,
     )]));
-    let mut column_hash_data = unf_hash
-        .finalize()
-        .into_iter()
-        .map(|x| x.short_hash)
+    let mut column_hash_data = columns
+        .iter()
+        .map(|x| x.unf.short_hash.clone())
         .collect::<Vec<String>>();
     column_hash_data.sort();
     let column_hashes = RecordBatch::try_new(
         schema.clone(),
         vec![Arc::new(StringArray::from(column_hash_data))],
-    )
-    .unwrap();
-    unf_from_batch(column_hashes, &schema, config)
+    )?;
+    Ok(DatasetUnf {
+        unf: unf_from_batch(column_hashes, &schema, config)?,
+        columns,
+        rows,
+    })
 }
 
+#[cfg(test)]
 mod tests {
     use crate::{config::UnfConfigBuilder, utils::read_csv_data};
+    use arrow::array::{ArrayRef, Float64Array, Int32Array};
+    use sha2::Digest;
 
     use super::*;
 
@@ -53,12 +88,10 @@ mod tests {
     fn load_float_from_file() {
         let file_path = "data/ExampleData.csv";
         let config = UnfConfigBuilder::new().build();
-        let csv = read_csv_data(file_path.to_string(), 100);
-        let mut unf_hash = UnfHashBuilder::new(csv.schema(), config::UnfVersion::Six, config);
+        let csv = read_csv_data(file_path.to_string(), 100).unwrap();
+        let mut unf_hash = UnfHashBuilder::new(csv.schema(), config);
         for batch in csv {
-            if let Ok(batch) = batch {
-                unf_hash.hash(batch);
-            }
+            unf_hash.hash(batch.unwrap()).unwrap();
         }
         let schema = Arc::new(Schema::new(vec![Field::new(
             "ColumnHashes",
@@ -67,6 +100,7 @@ mod tests {
         )]));
         let mut column_hash_data = unf_hash
             .finalize()
+            .unwrap()
             .into_iter()
             .map(|x| x.short_hash)
             .collect::<Vec<String>>();
@@ -76,7 +110,125 @@ mod tests {
             vec![Arc::new(StringArray::from(column_hash_data))],
         )
         .unwrap();
-        let res = unf_from_batch(column_hashes, &schema, config);
+        let res = unf_from_batch(column_hashes, &schema, config).unwrap();
         assert_eq!(res.short_hash, "Isf0CgUVrEZzLZdf5G46TA==".to_string());
     }
+
+    /// Dataset UNF of the example data along with the sorted UNFs of its columns
+    fn example_dataset_unf(version: config::UnfVersion) -> (UnfHash, Vec<String>) {
+        let config = UnfConfigBuilder::new().version(version).build();
+        let csv = read_csv_data("data/ExampleData.csv".to_string(), 100).unwrap();
+        let unf_hash = UnfHashBuilder::new(csv.schema(), config);
+        let res = calculate_dataset_unf(unf_hash, csv).unwrap();
+        let mut column_unfs = res
+            .columns
+            .iter()
+            .map(|x| x.unf.short_hash.clone())
+            .collect::<Vec<String>>();
+        column_unfs.sort();
+        (res.unf, column_unfs)
+    }
+
+    #[test]
+    fn load_float_from_file_v3() {
+        // The dataset UNF is the MD5 of the sorted column UNFs, each encoded as UTF-32BE
+        // and terminated by an end of line and a null byte
+        let (unf, column_unfs) = example_dataset_unf(config::UnfVersion::Three);
+        assert_eq!(unf.hash.len(), 16);
+        assert_eq!(unf.to_string(), format!("UNF:3:{}", unf.short_hash));
+        let encoded = column_unfs
+            .iter()
+            .flat_map(|x| format!("{}\n\0", x).chars().collect::<Vec<char>>())
+            .flat_map(|x| u32::from(x).to_be_bytes())
+            .collect::<Vec<u8>>();
+        assert_eq!(unf.hash, md5::Md5::digest(&encoded).to_vec());
+    }
+
+    #[test]
+    fn load_float_from_file_v4() {
+        // UNF V4 hashes the sorted column UNFs with SHA256, encoded as UTF-32BE, while V4.1
+        // encodes them as UTF-8
+        let (unf, column_unfs) = example_dataset_unf(config::UnfVersion::Four);
+        let encoded = column_unfs
+            .iter()
+            .flat_map(|x| format!("{}\n\0", x).chars().collect::<Vec<char>>())
+            .flat_map(|x| u32::from(x).to_be_bytes())
+            .collect::<Vec<u8>>();
+        assert_eq!(unf.hash, sha2::Sha256::digest(&encoded)[..16].to_vec());
+
+        let (unf, column_unfs) = example_dataset_unf(config::UnfVersion::FourOne);
+        let encoded = column_unfs
+            .iter()
+            .map(|x| format!("{}\n\0", x))
+            .collect::<String>();
+        assert_eq!(
+            unf.hash,
+            sha2::Sha256::digest(encoded.as_bytes())[..16].to_vec()
+        );
+        assert_eq!(unf.to_string(), format!("UNF:4.1:{}", unf.short_hash));
+    }
+
+    #[test]
+    fn load_float_from_file_v5() {
+        // UNF V5 hashes the sorted column UNFs with SHA256, encoded as UTF-8, and its
+        // columns round to nearest even rather than toward zero as V4.1 does
+        let (unf, column_unfs) = example_dataset_unf(config::UnfVersion::Five);
+        let encoded = column_unfs
+            .iter()
+            .map(|x| format!("{}\n\0", x))
+            .collect::<String>();
+        assert_eq!(
+            unf.hash,
+            sha2::Sha256::digest(encoded.as_bytes())[..16].to_vec()
+        );
+        assert_eq!(unf.to_string(), format!("UNF:5:{}", unf.short_hash));
+        let (_, four_one_unfs) = example_dataset_unf(config::UnfVersion::FourOne);
+        assert_ne!(column_unfs, four_one_unfs);
+    }
+
+    #[test]
+    fn r_reference_fingerprints() {
+        // The R UNF package documents unf(1:20) as UNF:6:/FIOZM/29oC3TK/IE52m2A==, which
+        // integer and float columns of the same values share
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from((1..=20).collect::<Vec<i32>>())),
+            Arc::new(Float64Array::from(
+                (1..=20).map(f64::from).collect::<Vec<f64>>(),
+            )),
+        ];
+        for column in columns {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "x",
+                column.data_type().clone(),
+                false,
+            )]));
+            let batch = RecordBatch::try_new(schema.clone(), vec![column]).unwrap();
+            let config = UnfConfigBuilder::new().build();
+            let res = calculate_dataset_unf(
+                UnfHashBuilder::new(schema, config),
+                vec![Ok(batch)].into_iter(),
+            )
+            .unwrap();
+            assert_eq!(
+                res.columns[0].unf.to_string(),
+                "UNF:6:/FIOZM/29oC3TK/IE52m2A=="
+            );
+        }
+    }
+
+    #[test]
+    fn column_unfs() {
+        let config = UnfConfigBuilder::new().build();
+        let csv = read_csv_data("data/ExampleData.csv".to_string(), 100).unwrap();
+        let schema = csv.schema();
+        let unf_hash = UnfHashBuilder::new(schema.clone(), config);
+        let res = calculate_dataset_unf(unf_hash, csv).unwrap();
+        assert_eq!(res.unf.short_hash, "Isf0CgUVrEZzLZdf5G46TA==");
+        assert_eq!(res.columns.len(), schema.fields().len());
+        assert_eq!(res.rows, 5000);
+        for (column, field) in res.columns.iter().zip(schema.fields()) {
+            assert_eq!(&column.name, field.name());
+            assert_eq!(&column.data_type, field.data_type());
+        }
+    }
 }
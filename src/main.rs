@@ -1,17 +1,50 @@
-use clap::{App, Arg};
+use std::{fs::File, io, path::Path, process, str::FromStr};
 
-use unfhash::calculate_unf;
-use unfhash::config::{UnfConfigBuilder, UnfVersion};
-use unfhash::hash_builder::UnfHashBuilder;
-use unfhash::utils::read_csv_data;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use serde_json::json;
+
+use arrow::{
+    datatypes::{DataType, Field, IntervalUnit, TimeUnit},
+    record_batch::RecordBatchReader,
+};
+use unfhash::calculate_dataset_unf;
+use unfhash::config::{UnfConfig, UnfConfigBuilder, UnfVersion};
+use unfhash::error::{Result, UnfError};
+use unfhash::hash_builder::{DatasetUnf, UnfHash, UnfHashBuilder};
+#[cfg(feature = "parquet")]
+use unfhash::utils::read_parquet_data;
+use unfhash::utils::{
+    read_csv_data, read_csv_temporal, read_ipc_file, read_ipc_stream, read_ndjson_data,
+    read_spss_data, read_stata_data, TemporalType,
+};
+
+/// Rows read at a time from formats that are read in batches
+const BATCH_SIZE: usize = 8192;
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Parse the value of an argument, reporting the argument when it is invalid
+fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<T> {
+    let value = matches.value_of(name).unwrap_or_default();
+    value
+        .parse()
+        .map_err(|_| UnfError::InvalidConfig(format!("Invalid {} '{}'", name, value)))
+}
+
+fn run() -> Result<()> {
     let matches = App::new("Unf")
         .version("0.0.1")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("input_file")
                 .short("i")
                 .value_name("FILE")
+                .required(true)
                 .takes_value(true),
         )
         .arg(
@@ -35,29 +68,410 @@ fn main() {
                 .default_value("128")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("unf_version")
+                .short("u")
+                .long("unf-version")
+                .value_name("UNF_VERSION")
+                .possible_values(&["3", "4", "4.1", "5", "6"])
+                .default_value("6")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rounding")
+                .long("rounding")
+                .value_name("ROUNDING")
+                .possible_values(&["nearest-even", "toward-zero"])
+                .global(true)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("inference_rows")
                 .short("r")
                 .value_name("INFERENCE_ROWS")
                 .default_value("100")
+                .global(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("temporal")
+                .short("T")
+                .long("temporal")
+                .value_name("COLUMN=TYPE")
+                .help("Read a column as date, time, datetime or zoned-datetime")
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("input_format")
+                .long("input-format")
+                .value_name("FORMAT")
+                .help("Read the input as this format rather than choosing by its extension")
+                .possible_values(&[
+                    "csv",
+                    "ndjson",
+                    "parquet",
+                    "ipc",
+                    "feather",
+                    "ipc-stream",
+                    "stata",
+                    "spss",
+                ])
+                .global(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("select")
+                .long("select")
+                .value_name("COLUMN")
+                .help("Read only the named column of a Parquet file")
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .help("Print the UNF of every column as a table")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check a file against a published UNF, such as UNF:6:<base64>")
+                .arg(Arg::with_name("file").value_name("FILE").required(true))
+                .arg(Arg::with_name("expected").value_name("UNF").required(true)),
+        )
         .get_matches();
-    let file_path = matches.value_of("input_file").unwrap();
-    let truncation = matches.value_of("truncation").unwrap();
-    let digits = matches.value_of("digits").unwrap();
-    let characters = matches.value_of("characters").unwrap();
-    let inference_rows: usize = matches.value_of("inference_rows").unwrap().parse().unwrap();
-    let config = UnfConfigBuilder::new()
-        .truncation(truncation.parse().unwrap())
-        .digits(digits.parse().unwrap())
-        .characters(characters.parse().unwrap())
-        .build();
-    let csv = read_csv_data(file_path.to_string(), inference_rows);
-    let unf_hash = UnfHashBuilder::new(csv.schema(), UnfVersion::Six, config);
-    let res = calculate_unf(unf_hash, csv.flatten(), config);
-    println!(
-        "File: {} | UNF Version: {:?} | ShortHash: {}",
-        file_path, config.version, res.short_hash
-    );
+    if let Some(verify) = matches.subcommand_matches("verify") {
+        return verify_file(verify);
+    }
+    let file_path = matches.value_of("input_file").unwrap_or_default();
+    let mut builder = UnfConfigBuilder::new();
+    builder
+        .truncation(parse_arg(&matches, "truncation")?)
+        .digits(parse_arg(&matches, "digits")?)
+        .characters(parse_arg(&matches, "characters")?)
+        .version(parse_arg::<UnfVersion>(&matches, "unf_version")?);
+    if matches.is_present("rounding") {
+        builder.rounding(parse_arg(&matches, "rounding")?);
+    }
+    let config = builder.try_build()?;
+    let res = read_unf(&matches, file_path, config)?;
+    if matches.value_of("format") == Some("json") {
+        println!("{}", json_report(file_path, &res));
+        return Ok(());
+    }
+    println!("File: {} | {}", file_path, res.unf);
+    if matches.is_present("columns") {
+        print_columns(&res);
+    }
+    Ok(())
+}
+
+/// Recompute the UNF of a file with the version and parameters of a published UNF
+fn verify_file(matches: &ArgMatches) -> Result<()> {
+    let file_path = matches.value_of("file").unwrap_or_default();
+    let expected: UnfHash = matches.value_of("expected").unwrap_or_default().parse()?;
+    let mut config = expected.config;
+    if matches.is_present("rounding") {
+        config.rounding = parse_arg(matches, "rounding")?;
+    }
+    let res = read_unf(matches, file_path, config)?;
+    if matches.is_present("columns") {
+        print_columns(&res);
+    }
+    if res.unf.short_hash == expected.short_hash {
+        println!("File: {} | {} | OK", file_path, res.unf);
+        Ok(())
+    } else {
+        eprintln!(
+            "File: {} | Mismatch: expected {} but found {}",
+            file_path, expected, res.unf
+        );
+        process::exit(1);
+    }
+}
+
+/// Short, stable name of a column type for reports, such as `int64` or `list<utf8>`
+fn type_name(data_type: &DataType) -> String {
+    let unit = |unit: &TimeUnit| match unit {
+        TimeUnit::Second => "s",
+        TimeUnit::Millisecond => "ms",
+        TimeUnit::Microsecond => "us",
+        TimeUnit::Nanosecond => "ns",
+    };
+    let fields = |fields: &[Field]| {
+        fields
+            .iter()
+            .map(|x| format!("{}: {}", x.name(), type_name(x.data_type())))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    match data_type {
+        DataType::Null => "null".to_string(),
+        DataType::Boolean => "bool".to_string(),
+        DataType::Int8 => "int8".to_string(),
+        DataType::Int16 => "int16".to_string(),
+        DataType::Int32 => "int32".to_string(),
+        DataType::Int64 => "int64".to_string(),
+        DataType::UInt8 => "uint8".to_string(),
+        DataType::UInt16 => "uint16".to_string(),
+        DataType::UInt32 => "uint32".to_string(),
+        DataType::UInt64 => "uint64".to_string(),
+        DataType::Float16 => "float16".to_string(),
+        DataType::Float32 => "float32".to_string(),
+        DataType::Float64 => "float64".to_string(),
+        DataType::Timestamp(time_unit, None) => format!("timestamp[{}]", unit(time_unit)),
+        DataType::Timestamp(time_unit, Some(zone)) => {
+            format!("timestamp[{}, {}]", unit(time_unit), zone)
+        }
+        DataType::Date32 => "date32".to_string(),
+        DataType::Date64 => "date64".to_string(),
+        DataType::Time32(time_unit) => format!("time32[{}]", unit(time_unit)),
+        DataType::Time64(time_unit) => format!("time64[{}]", unit(time_unit)),
+        DataType::Duration(time_unit) => format!("duration[{}]", unit(time_unit)),
+        DataType::Interval(IntervalUnit::YearMonth) => "interval[year_month]".to_string(),
+        DataType::Interval(IntervalUnit::DayTime) => "interval[day_time]".to_string(),
+        DataType::Binary => "binary".to_string(),
+        DataType::LargeBinary => "large_binary".to_string(),
+        DataType::FixedSizeBinary(size) => format!("fixed_size_binary[{}]", size),
+        DataType::Utf8 => "utf8".to_string(),
+        DataType::LargeUtf8 => "large_utf8".to_string(),
+        DataType::List(item) => format!("list<{}>", type_name(item.data_type())),
+        DataType::LargeList(item) => format!("large_list<{}>", type_name(item.data_type())),
+        DataType::FixedSizeList(item, size) => {
+            format!("fixed_size_list<{}>[{}]", type_name(item.data_type()), size)
+        }
+        DataType::Struct(children) => format!("struct<{}>", fields(children)),
+        DataType::Union(children) => format!("union<{}>", fields(children)),
+        DataType::Dictionary(_, value_type) => {
+            format!("dictionary<{}>", type_name(value_type))
+        }
+        DataType::Decimal(precision, scale) => format!("decimal({}, {})", precision, scale),
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(children) if children.len() == 2 => format!(
+                "map<{}, {}>",
+                type_name(children[0].data_type()),
+                type_name(children[1].data_type())
+            ),
+            entries => format!("map<{}>", type_name(entries)),
+        },
+    }
+}
+
+/// Print the name, type and UNF of every column, aligned as a table
+fn print_columns(res: &DatasetUnf) {
+    let rows = res
+        .columns
+        .iter()
+        .map(|x| [x.name.clone(), type_name(&x.data_type), x.unf.to_string()])
+        .collect::<Vec<[String; 3]>>();
+    let header = ["Column".to_string(), "Type".to_string(), "UNF".to_string()];
+    let widths = [0, 1].map(|index| {
+        rows.iter()
+            .chain(std::iter::once(&header))
+            .map(|x| x[index].chars().count())
+            .max()
+            .unwrap_or_default()
+    });
+    for [name, data_type, unf] in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:name_width$}  {:type_width$}  {}",
+            name,
+            data_type,
+            unf,
+            name_width = widths[0],
+            type_width = widths[1]
+        );
+    }
+}
+
+/// Describe the UNF of a file and of each of its columns as JSON
+fn json_report(file_path: &str, res: &DatasetUnf) -> serde_json::Value {
+    let config = res.unf.config;
+    // A computed UNF always keeps its complete digest
+    let digest = res.unf.digest.clone().unwrap_or_default();
+    json!({
+        "file": file_path,
+        "version": config.version.to_string(),
+        "parameters": {
+            "digits": config.digits,
+            "characters": config.characters,
+            "truncation": config.truncation,
+            "rounding": config.rounding.to_string(),
+        },
+        "unf": res.unf.to_string(),
+        "hash_base64": base64::encode(&digest),
+        "hash_hex": digest.iter().map(|x| format!("{:02x}", x)).collect::<String>(),
+        "rows": res.rows,
+        "columns": res
+            .columns
+            .iter()
+            .map(|x| {
+                json!({
+                    "name": x.name,
+                    "type": type_name(&x.data_type),
+                    "unf": x.unf.to_string(),
+                })
+            })
+            .collect::<Vec<serde_json::Value>>(),
+    })
+}
+
+/// Read a file and calculate its UNF
+///
+/// The reader is chosen by `--input-format`, or otherwise by the file extension, and `-`
+/// reads an Arrow IPC stream from standard input.
+fn read_unf(matches: &ArgMatches, file_path: &str, config: UnfConfig) -> Result<DatasetUnf> {
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_ascii_lowercase());
+    let input_format =
+        match (matches.value_of("input_format"), extension.as_deref()) {
+            (Some(input_format), _) => input_format,
+            (None, _) if file_path == "-" => "ipc-stream",
+            (None, Some("parquet")) | (None, Some("pq")) => "parquet",
+            (None, Some("arrow")) | (None, Some("feather")) | (None, Some("ipc")) => "ipc",
+            (None, Some("arrows")) => "ipc-stream",
+            (None, Some("ndjson")) | (None, Some("jsonl")) => "ndjson",
+            // A .json file is as likely to hold a single document as one record per line
+            (None, Some("json")) => return Err(UnfError::InvalidConfig(
+                "Only newline-delimited JSON can be read; use --input-format ndjson if each line \
+                 of the file is a record"
+                    .to_string(),
+            )),
+            (None, Some("dta")) => "stata",
+            (None, Some("sav")) | (None, Some("zsav")) => "spss",
+            (None, _) => "csv",
+        };
+    if input_format != "parquet" && matches.is_present("select") {
+        return Err(UnfError::InvalidConfig(
+            "Columns can only be selected from Parquet files".to_string(),
+        ));
+    }
+    if input_format != "csv" && matches.is_present("temporal") {
+        return Err(UnfError::InvalidConfig(
+            "Temporal columns can only be read from CSV files".to_string(),
+        ));
+    }
+    match input_format {
+        "parquet" => read_parquet_unf(matches, file_path, config),
+        "ipc" | "feather" => batch_unf(read_ipc_file(file_path.to_string())?, config),
+        "ipc-stream" if file_path == "-" => batch_unf(read_ipc_stream(io::stdin())?, config),
+        "ipc-stream" => batch_unf(read_ipc_stream(File::open(file_path)?)?, config),
+        "stata" => batch_unf(read_stata_data(file_path.to_string(), BATCH_SIZE)?, config),
+        "spss" => batch_unf(read_spss_data(file_path.to_string(), BATCH_SIZE)?, config),
+        "ndjson" => {
+            let ndjson = read_ndjson_data(file_path.to_string())?;
+            let unf_hash = UnfHashBuilder::new(ndjson.schema(), config);
+            calculate_dataset_unf(unf_hash, ndjson)
+        }
+        _ => read_csv_unf(matches, file_path, config),
+    }
+}
+
+/// Calculate the UNF of every batch of a reader
+fn batch_unf<R: RecordBatchReader>(reader: R, config: UnfConfig) -> Result<DatasetUnf> {
+    let unf_hash = UnfHashBuilder::new(reader.schema(), config);
+    calculate_dataset_unf(unf_hash, reader)
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet_unf(
+    matches: &ArgMatches,
+    file_path: &str,
+    config: UnfConfig,
+) -> Result<DatasetUnf> {
+    let columns = matches
+        .values_of("select")
+        .map(|values| values.map(|x| x.to_string()).collect::<Vec<String>>());
+    let parquet = read_parquet_data(file_path.to_string(), columns.as_deref(), BATCH_SIZE)?;
+    batch_unf(parquet, config)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet_unf(_: &ArgMatches, _: &str, _: UnfConfig) -> Result<DatasetUnf> {
+    Err(UnfError::InvalidConfig(
+        "Parquet support is not enabled in this build; rebuild with --features parquet".to_string(),
+    ))
+}
+
+/// Read a CSV file and calculate its UNF
+fn read_csv_unf(matches: &ArgMatches, file_path: &str, config: UnfConfig) -> Result<DatasetUnf> {
+    let inference_rows: usize = parse_arg(matches, "inference_rows")?;
+    let temporal_columns = matches
+        .values_of("temporal")
+        .map(|values| {
+            values
+                .map(|x| {
+                    let invalid =
+                        || UnfError::InvalidConfig(format!("Invalid temporal column '{}'", x));
+                    let (name, temporal_type) = x.rsplit_once('=').ok_or_else(invalid)?;
+                    let temporal_type = temporal_type.parse().map_err(|_| invalid())?;
+                    Ok((name.to_string(), temporal_type))
+                })
+                .collect::<Result<Vec<(String, TemporalType)>>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+    Ok(if temporal_columns.is_empty() {
+        let csv = read_csv_data(file_path.to_string(), inference_rows)?;
+        let unf_hash = UnfHashBuilder::new(csv.schema(), config);
+        calculate_dataset_unf(unf_hash, csv)?
+    } else {
+        let csv = read_csv_temporal(file_path.to_string(), inference_rows, &temporal_columns)?;
+        let unf_hash = UnfHashBuilder::new(csv.schema(), config);
+        calculate_dataset_unf(unf_hash, csv)?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_names() {
+        assert_eq!(type_name(&DataType::Int64), "int64");
+        assert_eq!(
+            type_name(&DataType::Timestamp(
+                TimeUnit::Millisecond,
+                Some("UTC".to_string())
+            )),
+            "timestamp[ms, UTC]"
+        );
+        assert_eq!(
+            type_name(&DataType::List(Box::new(Field::new(
+                "item",
+                DataType::Utf8,
+                true
+            )))),
+            "list<utf8>"
+        );
+        assert_eq!(
+            type_name(&DataType::Struct(vec![
+                Field::new("a", DataType::Int32, true),
+                Field::new("b", DataType::Decimal(10, 2), true),
+            ])),
+            "struct<a: int32, b: decimal(10, 2)>"
+        );
+        assert_eq!(
+            type_name(&DataType::Dictionary(
+                Box::new(DataType::Int32),
+                Box::new(DataType::Utf8)
+            )),
+            "dictionary<utf8>"
+        );
+    }
 }
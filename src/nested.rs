@@ -0,0 +1,665 @@
+use std::ops::Range;
+
+use crate::{
+    config::{ListMode, UnfConfig},
+    error::Result,
+    hash_builder::{check_schema, column_raw, downcast, unf_vector},
+    unf_vector::{terminated, MISSING_VALUE},
+};
+use arrow::{
+    array::{
+        Array, ArrayRef, FixedSizeListArray, LargeListArray, ListArray, StructArray, UnionArray,
+    },
+    compute::cast,
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use sha2::Digest;
+
+/// Encoded values of a leaf column, grouped by the top-level row they belong to
+type LeafRows = Vec<Vec<Vec<u8>>>;
+
+/// Whether a column is flattened or serialized rather than normalized directly
+pub(crate) fn is_nested(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Struct(_)
+        | DataType::Union(_)
+        | DataType::List(_)
+        | DataType::LargeList(_)
+        | DataType::FixedSizeList(_, _) => true,
+        DataType::Dictionary(_, value_type) => is_nested(value_type),
+        _ => false,
+    }
+}
+
+/// Schema of the leaf columns that a schema flattens into
+///
+/// Struct and union fields become dotted leaf columns, such as `address.city`. Exploded lists
+/// contribute the leaves of their items under the list's own name, while serialized lists
+/// remain a single leaf column.
+pub(crate) fn leaf_schema(schema: &Schema, list_mode: ListMode) -> Schema {
+    Schema::new(
+        schema
+            .fields()
+            .iter()
+            .flat_map(|field| {
+                leaves(field.data_type(), list_mode)
+                    .into_iter()
+                    .map(move |(path, data_type)| {
+                        let name = match path {
+                            Some(path) => format!("{}.{}", field.name(), path),
+                            None => field.name().to_string(),
+                        };
+                        Field::new(&name, data_type, true)
+                    })
+            })
+            .collect(),
+    )
+}
+
+/// Leaf columns of a data type, as their path below the column and their data type
+fn leaves(data_type: &DataType, list_mode: ListMode) -> Vec<(Option<String>, DataType)> {
+    match data_type {
+        DataType::Struct(fields) | DataType::Union(fields) => fields
+            .iter()
+            .flat_map(|field| {
+                leaves(field.data_type(), list_mode)
+                    .into_iter()
+                    .map(move |(path, data_type)| match path {
+                        Some(path) => (Some(format!("{}.{}", field.name(), path)), data_type),
+                        None => (Some(field.name().to_string()), data_type),
+                    })
+            })
+            .collect(),
+        DataType::List(item) | DataType::LargeList(item) | DataType::FixedSizeList(item, _)
+            if list_mode == ListMode::Explode =>
+        {
+            leaves(item.data_type(), list_mode)
+        }
+        DataType::Dictionary(_, value_type) if is_nested(value_type) => {
+            leaves(value_type, list_mode)
+        }
+        _ => vec![(None, data_type.clone())],
+    }
+}
+
+/// Update leaf column Hashes for a given RecordBatch
+///
+/// Assumes that the ordering of the hashes matches the ordering of the leaf schema.
+pub(crate) fn unf_leaf_batch<D: Digest>(
+    input: RecordBatch,
+    schema: &Schema,
+    config: UnfConfig,
+    list_mode: ListMode,
+    hash: &mut [D],
+) -> Result<()> {
+    check_schema(&input, schema)?;
+    let mut hashers = hash.iter_mut();
+    for (column, field) in input.columns().iter().zip(schema.fields()) {
+        for (rows, hasher) in leaf_rows(column, field.data_type(), &config, list_mode)?
+            .into_iter()
+            .zip(&mut hashers)
+        {
+            for value in rows.into_iter().flatten() {
+                hasher.update(value)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Encode the values of every leaf column of a column
+///
+/// A missing struct marks each of its leaves as missing for that row, and a union marks
+/// the leaves of every field other than the one it holds as missing. When lists are
+/// exploded, a missing list contributes a single missing value to each of its leaves, and
+/// an empty list contributes no values.
+fn leaf_rows(
+    column: &ArrayRef,
+    data_type: &DataType,
+    config: &UnfConfig,
+    list_mode: ListMode,
+) -> Result<Vec<LeafRows>> {
+    match data_type {
+        DataType::Dictionary(_, value_type) if is_nested(value_type) => {
+            leaf_rows(&cast(column, value_type)?, value_type, config, list_mode)
+        }
+        DataType::Struct(fields) => {
+            let array = downcast::<StructArray>(column)?;
+            let mut leaves = Vec::with_capacity(fields.len());
+            for (index, field) in fields.iter().enumerate() {
+                leaves.extend(leaf_rows(
+                    array.column(index),
+                    field.data_type(),
+                    config,
+                    list_mode,
+                )?);
+            }
+            for rows in leaves.iter_mut() {
+                for (row, values) in rows.iter_mut().enumerate() {
+                    if array.is_null(row) {
+                        *values = vec![MISSING_VALUE.to_vec()];
+                    }
+                }
+            }
+            Ok(leaves)
+        }
+        DataType::Union(fields) => {
+            let array = downcast::<UnionArray>(column)?;
+            let mut leaves = Vec::new();
+            for (index, field) in fields.iter().enumerate() {
+                let child = array.child(index as i8);
+                for child_rows in leaf_rows(&child, field.data_type(), config, list_mode)? {
+                    leaves.push(
+                        (0..array.len())
+                            .map(|row| match union_value(array, row) {
+                                Some((field, offset)) if field == index => {
+                                    child_rows[offset].clone()
+                                }
+                                _ => vec![MISSING_VALUE.to_vec()],
+                            })
+                            .collect(),
+                    );
+                }
+            }
+            Ok(leaves)
+        }
+        DataType::List(item) if list_mode == ListMode::Explode => {
+            let array = downcast::<ListArray>(column)?;
+            exploded(array, &array.values(), item, config, |row| {
+                let offsets = array.value_offsets();
+                offsets[row] as usize..offsets[row + 1] as usize
+            })
+        }
+        DataType::LargeList(item) if list_mode == ListMode::Explode => {
+            let array = downcast::<LargeListArray>(column)?;
+            exploded(array, &array.values(), item, config, |row| {
+                let offsets = array.value_offsets();
+                offsets[row] as usize..offsets[row + 1] as usize
+            })
+        }
+        DataType::FixedSizeList(item, _) if list_mode == ListMode::Explode => {
+            let array = downcast::<FixedSizeListArray>(column)?;
+            exploded(array, &array.values(), item, config, |row| {
+                let start = array.value_offset(row) as usize;
+                start..start + array.value_length() as usize
+            })
+        }
+        _ => Ok(vec![column_raw(column, data_type, config)?
+            .into_iter()
+            .map(|value| vec![value])
+            .collect()]),
+    }
+}
+
+/// Field index and child offset of the value that a union holds in a row, if any
+fn union_value(array: &UnionArray, row: usize) -> Option<(usize, usize)> {
+    if array.is_null(row) {
+        return None;
+    }
+    let index = array.offset() + row;
+    Some((
+        array.type_id(index) as usize,
+        array.value_offset(index) as usize,
+    ))
+}
+
+/// Encode the leaves of list elements, grouped by the list that holds them
+fn exploded<F>(
+    array: &dyn Array,
+    values: &ArrayRef,
+    item: &Field,
+    config: &UnfConfig,
+    elements: F,
+) -> Result<Vec<LeafRows>>
+where
+    F: Fn(usize) -> Range<usize>,
+{
+    Ok(
+        leaf_rows(values, item.data_type(), config, ListMode::Explode)?
+            .into_iter()
+            .map(|element_rows| {
+                (0..array.len())
+                    .map(|row| {
+                        if array.is_null(row) {
+                            vec![MISSING_VALUE.to_vec()]
+                        } else {
+                            element_rows[elements(row)].concat()
+                        }
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Encode nested values as their canonical serialization
+///
+/// Serialized values are not character strings, so `characters` does not truncate them.
+pub(crate) fn serialized_raw(
+    column: &ArrayRef,
+    data_type: &DataType,
+    config: &UnfConfig,
+) -> Result<Vec<Vec<u8>>> {
+    Ok(serialized(column, data_type, config)?
+        .into_iter()
+        .map(|x| match x {
+            Some(x) => terminated(x.chars().collect(), config.version),
+            None => MISSING_VALUE.to_vec(),
+        })
+        .collect())
+}
+
+/// Serialize each value as canonical JSON, with missing values represented as `None`
+///
+/// Lists become arrays and structs become objects with their fields in schema order.
+/// A union becomes an object whose single member is the field it holds. Every other
+/// value is serialized as the string of its normalized form, so that `[1, null]` of
+/// integers is serialized as `["+1.e+",null]`.
+fn serialized(
+    column: &ArrayRef,
+    data_type: &DataType,
+    config: &UnfConfig,
+) -> Result<Vec<Option<String>>> {
+    match data_type {
+        DataType::Dictionary(_, value_type) => {
+            serialized(&cast(column, value_type)?, value_type, config)
+        }
+        DataType::Struct(fields) => {
+            let array = downcast::<StructArray>(column)?;
+            let children = (0..fields.len())
+                .map(|index| serialized(array.column(index), fields[index].data_type(), config))
+                .collect::<Result<Vec<_>>>()?;
+            Ok((0..array.len())
+                .map(|row| {
+                    if array.is_null(row) {
+                        None
+                    } else {
+                        let members = fields
+                            .iter()
+                            .zip(&children)
+                            .map(|(field, values)| {
+                                format!(
+                                    "{}:{}",
+                                    json_string(field.name()),
+                                    json_value(&values[row])
+                                )
+                            })
+                            .collect::<Vec<String>>();
+                        Some(format!("{{{}}}", members.join(",")))
+                    }
+                })
+                .collect())
+        }
+        DataType::Union(fields) => {
+            let array = downcast::<UnionArray>(column)?;
+            let children = (0..fields.len())
+                .map(|index| {
+                    serialized(&array.child(index as i8), fields[index].data_type(), config)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((0..array.len())
+                .map(|row| {
+                    union_value(array, row).map(|(index, offset)| {
+                        format!(
+                            "{{{}:{}}}",
+                            json_string(fields[index].name()),
+                            json_value(&children[index][offset])
+                        )
+                    })
+                })
+                .collect())
+        }
+        DataType::List(item) => {
+            let array = downcast::<ListArray>(column)?;
+            serialized_lists(array, &array.values(), item, config, |row| {
+                let offsets = array.value_offsets();
+                offsets[row] as usize..offsets[row + 1] as usize
+            })
+        }
+        DataType::LargeList(item) => {
+            let array = downcast::<LargeListArray>(column)?;
+            serialized_lists(array, &array.values(), item, config, |row| {
+                let offsets = array.value_offsets();
+                offsets[row] as usize..offsets[row + 1] as usize
+            })
+        }
+        DataType::FixedSizeList(item, _) => {
+            let array = downcast::<FixedSizeListArray>(column)?;
+            serialized_lists(array, &array.values(), item, config, |row| {
+                let start = array.value_offset(row) as usize;
+                start..start + array.value_length() as usize
+            })
+        }
+        _ => Ok(unf_vector(column, data_type)?
+            .to_unf(config)?
+            .into_iter()
+            .map(|x| x.map(|x| json_string(&x)))
+            .collect()),
+    }
+}
+
+/// Serialize each list as a JSON array of its serialized elements
+fn serialized_lists<F>(
+    array: &dyn Array,
+    values: &ArrayRef,
+    item: &Field,
+    config: &UnfConfig,
+    elements: F,
+) -> Result<Vec<Option<String>>>
+where
+    F: Fn(usize) -> Range<usize>,
+{
+    let values = serialized(values, item.data_type(), config)?;
+    Ok((0..array.len())
+        .map(|row| {
+            if array.is_null(row) {
+                None
+            } else {
+                let elements = values[elements(row)]
+                    .iter()
+                    .map(json_value)
+                    .collect::<Vec<String>>();
+                Some(format!("[{}]", elements.join(",")))
+            }
+        })
+        .collect())
+}
+
+fn json_value(value: &Option<String>) -> String {
+    match value {
+        Some(value) => value.clone(),
+        None => "null".to_string(),
+    }
+}
+
+/// Quote a string as a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            x if u32::from(x) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(x))),
+            x => quoted.push(x),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        calculate_unf,
+        config::UnfConfigBuilder,
+        hash_builder::{tests::column_hash, UnfHashBuilder},
+    };
+    use arrow::{
+        array::{Int32Array, StringArray, UnionBuilder},
+        buffer::Buffer,
+        datatypes::{Float64Type, Int32Type},
+    };
+
+    /// Struct of `{a: Int32, b: Utf8}` whose second row is missing
+    fn people() -> ArrayRef {
+        Arc::new(StructArray::from((
+            vec![
+                (
+                    Field::new("a", DataType::Int32, true),
+                    Arc::new(Int32Array::from(vec![Some(1), Some(2), None])) as ArrayRef,
+                ),
+                (
+                    Field::new("b", DataType::Utf8, true),
+                    Arc::new(StringArray::from(vec!["x", "y", "z"])) as ArrayRef,
+                ),
+            ],
+            Buffer::from([0b101]),
+        )))
+    }
+
+    /// Union of `{a: Int32, b: Float64}` whose third row is missing
+    fn readings(dense: bool) -> ArrayRef {
+        let mut builder = if dense {
+            UnionBuilder::new_dense(4)
+        } else {
+            UnionBuilder::new_sparse(4)
+        };
+        builder.append::<Int32Type>("a", 1).unwrap();
+        builder.append::<Float64Type>("b", 2.5).unwrap();
+        builder.append_null().unwrap();
+        builder.append::<Int32Type>("a", 3).unwrap();
+        Arc::new(builder.build().unwrap())
+    }
+
+    fn scores() -> ArrayRef {
+        Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), Some(2)]),
+            None,
+            Some(vec![]),
+            Some(vec![None]),
+        ]))
+    }
+
+    fn leaf_hashes(column: ArrayRef, list_mode: ListMode) -> Vec<String> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "column",
+            column.data_type().clone(),
+            true,
+        )]));
+        let config = UnfConfigBuilder::new().build();
+        let batch = RecordBatch::try_new(schema.clone(), vec![column]).unwrap();
+        let mut builder = UnfHashBuilder::new(schema, config).flatten_nested(list_mode);
+        builder.hash(batch).unwrap();
+        builder
+            .finalize()
+            .unwrap()
+            .into_iter()
+            .map(|x| x.short_hash)
+            .collect()
+    }
+
+    #[test]
+    fn struct_leaves_are_dotted() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("person", people().data_type().clone(), true),
+            Field::new("scores", scores().data_type().clone(), true),
+        ]);
+        let names = |list_mode| {
+            leaf_schema(&schema, list_mode)
+                .fields()
+                .iter()
+                .map(|x| x.name().clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            names(ListMode::Explode),
+            vec!["id", "person.a", "person.b", "scores"]
+        );
+        assert_eq!(
+            leaf_schema(&schema, ListMode::Explode).field(3).data_type(),
+            &DataType::Int32
+        );
+        assert!(matches!(
+            leaf_schema(&schema, ListMode::Serialize)
+                .field(3)
+                .data_type(),
+            DataType::List(_)
+        ));
+    }
+
+    #[test]
+    fn missing_struct_marks_leaves_missing() {
+        let hashes = leaf_hashes(people(), ListMode::Explode);
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(
+            hashes[0],
+            column_hash(Arc::new(Int32Array::from(vec![Some(1), None, None])))
+        );
+        assert_eq!(
+            hashes[1],
+            column_hash(Arc::new(StringArray::from(vec![
+                Some("x"),
+                None,
+                Some("z")
+            ])))
+        );
+    }
+
+    #[test]
+    fn sliced_struct() {
+        let sliced = people().slice(1, 2);
+        let hashes = leaf_hashes(sliced, ListMode::Explode);
+        assert_eq!(
+            hashes[0],
+            column_hash(Arc::new(Int32Array::from(vec![None, None])))
+        );
+    }
+
+    #[test]
+    fn exploded_lists() {
+        // The missing list and the missing element both contribute one missing value,
+        // while the empty list contributes nothing
+        let hashes = leaf_hashes(scores(), ListMode::Explode);
+        assert_eq!(
+            hashes,
+            vec![column_hash(Arc::new(Int32Array::from(vec![
+                Some(1),
+                Some(2),
+                None,
+                None
+            ])))]
+        );
+    }
+
+    #[test]
+    fn serialized_lists() {
+        let config = UnfConfigBuilder::new().build();
+        let column = scores();
+        assert_eq!(
+            serialized(&column, column.data_type(), &config).unwrap(),
+            vec![
+                Some(r#"["+1.e+","+2.e+"]"#.to_string()),
+                None,
+                Some("[]".to_string()),
+                Some("[null]".to_string())
+            ]
+        );
+        let hashes = leaf_hashes(scores(), ListMode::Serialize);
+        assert_eq!(hashes, vec![column_hash(scores())]);
+        assert_ne!(hashes, leaf_hashes(scores(), ListMode::Explode));
+    }
+
+    #[test]
+    fn serialized_structs() {
+        let config = UnfConfigBuilder::new().build();
+        let column = people();
+        assert_eq!(
+            serialized(&column, column.data_type(), &config).unwrap(),
+            vec![
+                Some(r#"{"a":"+1.e+","b":"x"}"#.to_string()),
+                None,
+                Some(r#"{"a":null,"b":"z"}"#.to_string())
+            ]
+        );
+        assert_eq!(json_string("a\"b\\\n\u{1}"), r#""a\"b\\\n\u0001""#);
+    }
+
+    #[test]
+    fn hashers_follow_leaf_schema() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("person", people().data_type().clone(), true),
+            Field::new("scores", scores().data_type().clone(), true),
+        ]));
+        let config = UnfConfigBuilder::new().build();
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![people(), scores().slice(0, 3)]).unwrap();
+        let mut builder =
+            UnfHashBuilder::new(schema.clone(), config).flatten_nested(ListMode::Explode);
+        builder.hash(batch.clone()).unwrap();
+        assert_eq!(builder.finalize().unwrap().len(), 3);
+        let flattened = calculate_unf(
+            UnfHashBuilder::new(schema.clone(), config).flatten_nested(ListMode::Serialize),
+            vec![Ok(batch.clone())].into_iter(),
+        )
+        .unwrap();
+        let nested = calculate_unf(
+            UnfHashBuilder::new(schema, config),
+            vec![Ok(batch)].into_iter(),
+        )
+        .unwrap();
+        assert_ne!(flattened.short_hash, nested.short_hash);
+    }
+
+    #[test]
+    fn union_leaves() {
+        for dense in [true, false] {
+            let hashes = leaf_hashes(readings(dense), ListMode::Explode);
+            assert_eq!(
+                hashes,
+                vec![
+                    column_hash(Arc::new(Int32Array::from(vec![
+                        Some(1),
+                        None,
+                        None,
+                        Some(3)
+                    ]))),
+                    column_hash(Arc::new(arrow::array::Float64Array::from(vec![
+                        None,
+                        Some(2.5),
+                        None,
+                        None
+                    ]))),
+                ]
+            );
+            assert_eq!(
+                leaf_hashes(readings(dense).slice(1, 3), ListMode::Explode)[0],
+                column_hash(Arc::new(Int32Array::from(vec![None, None, Some(3)])))
+            );
+        }
+        let schema = Schema::new(vec![Field::new(
+            "reading",
+            readings(true).data_type().clone(),
+            true,
+        )]);
+        assert_eq!(
+            leaf_schema(&schema, ListMode::Serialize)
+                .fields()
+                .iter()
+                .map(|x| x.name().clone())
+                .collect::<Vec<String>>(),
+            vec!["reading.a", "reading.b"]
+        );
+    }
+
+    #[test]
+    fn serialized_unions() {
+        let config = UnfConfigBuilder::new().build();
+        for dense in [true, false] {
+            let column = readings(dense);
+            assert_eq!(
+                serialized(&column, column.data_type(), &config).unwrap(),
+                vec![
+                    Some(r#"{"a":"+1.e+"}"#.to_string()),
+                    Some(r#"{"b":"+2.5e+"}"#.to_string()),
+                    None,
+                    Some(r#"{"a":"+3.e+"}"#.to_string())
+                ]
+            );
+            let sliced = column.slice(1, 2);
+            assert_eq!(
+                serialized(&sliced, sliced.data_type(), &config).unwrap(),
+                vec![Some(r#"{"b":"+2.5e+"}"#.to_string()), None]
+            );
+            assert_eq!(column_hash(column.clone()), column_hash(readings(!dense)));
+        }
+    }
+}
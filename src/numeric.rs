@@ -0,0 +1,455 @@
+use crate::config::Rounding;
+
+/// Precision that yields the exact decimal expansion of any finite `f64`
+///
+/// The longest exact expansion, that of the smallest subnormal, has 767 significant digits.
+const EXACT_PRECISION: usize = 767;
+
+/// Significant digits of the correctly rounded expansion that most values are rounded from
+///
+/// Seventeen digits are more than the 15 that may be kept, and distinguish every `f64`.
+const SHORT_PRECISION: usize = 17;
+
+/// Normalize values that are not represented in exponential notation
+///
+/// Zero keeps its sign, and infinities and NaN have fixed representations.
+pub(crate) fn special_form(value: f64) -> Option<String> {
+    if value.is_nan() {
+        Some("+nan".to_string())
+    } else if value.is_infinite() {
+        if value.is_sign_positive() {
+            Some("+inf".to_string())
+        } else {
+            Some("-inf".to_string())
+        }
+    } else if value == 0.0 {
+        if value.is_sign_positive() {
+            Some("+0.e+".to_string())
+        } else {
+            Some("-0.e+".to_string())
+        }
+    } else {
+        None
+    }
+}
+
+/// Normalize a floating point value to `digits` significant digits
+///
+/// Rounding is performed as if on the complete decimal expansion of the binary float, so no
+/// representation error is introduced regardless of the number of digits, and both
+/// rounding modes share the same rounding of decimal digits as integers and decimals.
+///
+/// Values are rounded from their correctly rounded 17 digit expansion, which rounds the
+/// same as the complete expansion unless its discarded digits sit on a rounding boundary:
+/// exactly a half for nearest-even, or zero for toward-zero, which may have been reached
+/// by rounding the expansion up. Only those values are expanded completely.
+pub(crate) fn float_form(value: f64, digits: u32, rounding: Rounding) -> String {
+    if let Some(special) = special_form(value) {
+        return special;
+    }
+    let digits = digits.max(1) as usize;
+    let (mut significand, mut exponent) = decimal_expansion(value.abs(), SHORT_PRECISION);
+    if digits >= SHORT_PRECISION || on_boundary(&significand[digits..], rounding) {
+        let exact = decimal_expansion(value.abs(), exact_precision(value));
+        significand = exact.0;
+        exponent = exact.1;
+    }
+    let (significand, exponent) = round_digits(&significand, exponent, digits, rounding);
+    exp_form(value.is_sign_negative(), &significand, exponent)
+}
+
+/// Significant digits and exponent of a positive finite value, correctly rounded to `precision`
+fn decimal_expansion(value: f64, precision: usize) -> (String, i64) {
+    let string_rep = format!("{:.*e}", precision - 1, value);
+    let (mantissa, exponent) = string_rep.split_once('e').unwrap();
+    (mantissa.replace('.', ""), exponent.parse().unwrap())
+}
+
+/// Whether the discarded digits of a correctly rounded expansion leave its rounding undecided
+fn on_boundary(discarded: &str, rounding: Rounding) -> bool {
+    match rounding {
+        Rounding::TowardZero => discarded.bytes().all(|x| x == b'0'),
+        Rounding::NearestEven => {
+            discarded.starts_with('5') && discarded[1..].bytes().all(|x| x == b'0')
+        }
+    }
+}
+
+/// Significant digits that are enough to expand a finite non-zero value exactly
+///
+/// A value `m * 2^e`, with `m` odd, has as many digits as the integer `m * 2^e` when `e` is
+/// positive, and as the integer `m * 5^-e` when it is negative.
+fn exact_precision(value: f64) -> usize {
+    let bits = value.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i64;
+    let fraction = bits & ((1 << 52) - 1);
+    let (mantissa, exponent) = if biased_exponent == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | (1 << 52), biased_exponent - 1075)
+    };
+    let shift = mantissa.trailing_zeros();
+    let mantissa_bits = f64::from(64 - (mantissa >> shift).leading_zeros());
+    let exponent = exponent + i64::from(shift);
+    // Upper bounds of log10(2) and log10(5)
+    let digits = if exponent >= 0 {
+        (mantissa_bits + exponent as f64) * 0.302
+    } else {
+        mantissa_bits * 0.302 + (-exponent) as f64 * 0.699
+    };
+    (digits as usize + 2).min(EXACT_PRECISION)
+}
+
+/// Normalize an integer to `digits` significant digits
+pub(crate) fn integer_form<T: ToString>(value: T, digits: u32, rounding: Rounding) -> String {
+    let string_rep = value.to_string();
+    let (negative, significand) = match string_rep.strip_prefix('-') {
+        Some(significand) => (true, significand),
+        None => (false, string_rep.as_str()),
+    };
+    scaled_form(negative, significand, 0, digits, rounding)
+}
+
+/// Normalize `significand * 10^-scale` to `digits` significant digits
+///
+/// The significand is a string of decimal digits, possibly with leading zeros.
+pub(crate) fn scaled_form(
+    negative: bool,
+    significand: &str,
+    scale: i64,
+    digits: u32,
+    rounding: Rounding,
+) -> String {
+    let significand = significand.trim_start_matches('0');
+    if significand.is_empty() {
+        return special_form(0.0).unwrap();
+    }
+    let exponent = significand.len() as i64 - 1 - scale;
+    let (significand, exponent) =
+        round_digits(significand, exponent, digits.max(1) as usize, rounding);
+    exp_form(negative, &significand, exponent)
+}
+
+/// Round a significand of decimal digits, with a leading non-zero digit, to `digits` digits
+fn round_digits(
+    significand: &str,
+    exponent: i64,
+    digits: usize,
+    rounding: Rounding,
+) -> (String, i64) {
+    if significand.len() <= digits {
+        return (significand.to_string(), exponent);
+    }
+    let (kept, remainder) = significand.split_at(digits);
+    let round_up = match rounding {
+        Rounding::TowardZero => false,
+        Rounding::NearestEven => {
+            let mut remainder = remainder.bytes();
+            match remainder.next() {
+                Some(b'5') => {
+                    remainder.any(|x| x != b'0') || (kept.as_bytes()[digits - 1] - b'0') % 2 == 1
+                }
+                Some(first) => first > b'5',
+                None => false,
+            }
+        }
+    };
+    if !round_up {
+        return (kept.to_string(), exponent);
+    }
+    let mut rounded = kept.as_bytes().to_vec();
+    for digit in rounded.iter_mut().rev() {
+        if *digit == b'9' {
+            *digit = b'0';
+        } else {
+            *digit += 1;
+            return (String::from_utf8(rounded).unwrap(), exponent);
+        }
+    }
+    // Every kept digit was a nine, so the value carries into a new leading digit
+    rounded.insert(0, b'1');
+    rounded.pop();
+    (String::from_utf8(rounded).unwrap(), exponent + 1)
+}
+
+/// Format a significand and exponent in the UNF exponential notation
+///
+/// Trailing zeros of the significand are removed, and a zero exponent is written as a bare sign.
+fn exp_form(negative: bool, significand: &str, exponent: i64) -> String {
+    let significand = significand.trim_end_matches('0');
+    let sign = if negative { '-' } else { '+' };
+    let formatted_exponent = match exponent {
+        0 => "+".to_string(),
+        x if x < 0 => x.to_string(),
+        x => format!("+{}", x),
+    };
+    format!(
+        "{}{}.{}e{}",
+        sign,
+        &significand[..1],
+        &significand[1..],
+        formatted_exponent
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Exact decimal expansion of a positive finite float, as significant digits and exponent
+    fn reference_expansion(value: f64) -> (String, i64) {
+        let bits = value.to_bits();
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mantissa, binary_exponent) = if biased_exponent == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased_exponent - 1075)
+        };
+        let (integer, decimal_shift) = if binary_exponent >= 0 {
+            (BigUint::from(mantissa) << binary_exponent as usize, 0)
+        } else {
+            let power = BigUint::from(5_u32).pow(-binary_exponent as u32);
+            (BigUint::from(mantissa) * power, -binary_exponent)
+        };
+        let digits = integer.to_string();
+        let exponent = digits.len() as i64 - 1 - decimal_shift;
+        (digits.trim_end_matches('0').to_string(), exponent)
+    }
+
+    /// Reference rounding, comparing the discarded digits against one half
+    fn reference_form(value: f64, digits: usize, rounding: Rounding) -> String {
+        let (expansion, mut exponent) = reference_expansion(value.abs());
+        let mut kept: Vec<u32> = expansion.chars().map(|x| x.to_digit(10).unwrap()).collect();
+        kept.resize(kept.len().max(digits), 0);
+        let discarded = kept.split_off(digits);
+        let half = std::iter::once(5).chain(std::iter::repeat(0));
+        let comparison = discarded
+            .iter()
+            .zip(half)
+            .map(|(x, y)| x.cmp(&y))
+            .find(|x| x.is_ne())
+            .unwrap_or(if discarded.is_empty() {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Equal
+            });
+        let round_up = rounding == Rounding::NearestEven
+            && (comparison.is_gt() || (comparison.is_eq() && kept[digits - 1] % 2 == 1));
+        if round_up {
+            let mut position = digits;
+            loop {
+                if position == 0 {
+                    kept.insert(0, 1);
+                    kept.pop();
+                    exponent += 1;
+                    break;
+                }
+                position -= 1;
+                if kept[position] == 9 {
+                    kept[position] = 0;
+                } else {
+                    kept[position] += 1;
+                    break;
+                }
+            }
+        }
+        while kept.len() > 1 && kept.last() == Some(&0) {
+            kept.pop();
+        }
+        let mantissa: String = kept[1..].iter().map(|x| x.to_string()).collect();
+        let exponent = match exponent {
+            0 => "+".to_string(),
+            x if x < 0 => x.to_string(),
+            x => format!("+{}", x),
+        };
+        format!(
+            "{}{}.{}e{}",
+            if value.is_sign_negative() { '-' } else { '+' },
+            kept[0],
+            mantissa,
+            exponent
+        )
+    }
+
+    /// Number of random doubles checked per rounding mode
+    ///
+    /// CI sets `UNF_PROPERTY_CASES` to check millions in a release build.
+    fn property_cases() -> usize {
+        std::env::var("UNF_PROPERTY_CASES")
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(100_000)
+    }
+
+    /// Float nearest to a decimal on a rounding boundary at `digits`, or one of its neighbours
+    ///
+    /// The decimal is either a tie, ending in a five after `digits` digits, or has exactly
+    /// `digits` digits.
+    fn near_boundary(rng: &mut StdRng, digits: u32) -> f64 {
+        let kept = rng.gen_range(10_u64.pow(digits - 1)..10_u64.pow(digits));
+        let tie = if rng.gen_bool(0.5) { "5" } else { "" };
+        let boundary: f64 = format!("{}{}e{}", kept, tie, rng.gen_range(-320..300))
+            .parse()
+            .unwrap();
+        f64::from_bits((boundary.to_bits() as i64 + rng.gen_range(-1..=1)) as u64)
+    }
+
+    fn check_agreement(rounding: Rounding, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..property_cases() {
+            let digits = rng.gen_range(1..=15);
+            let value = match rng.gen_range(0..3) {
+                0 => f64::from_bits(rng.gen()),
+                1 => rng.gen_range(-1.0e6..1.0e6),
+                _ => near_boundary(&mut rng, digits),
+            };
+            if !value.is_finite() || value == 0.0 {
+                continue;
+            }
+            assert_eq!(
+                float_form(value, digits, rounding),
+                reference_form(value, digits as usize, rounding),
+                "{:e} rounded to {} digits",
+                value,
+                digits
+            );
+        }
+    }
+
+    #[test]
+    fn nearest_even_agrees_with_reference() {
+        check_agreement(Rounding::NearestEven, 6);
+    }
+
+    #[test]
+    fn toward_zero_agrees_with_reference() {
+        check_agreement(Rounding::TowardZero, 7);
+    }
+
+    #[test]
+    fn exact_precision_expands_exactly() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let values = [f64::MIN_POSITIVE, 5e-324, f64::MAX, 1.0, 0.1, 1e23];
+        let random = (0..10_000).map(|_| f64::from_bits(rng.gen()).abs());
+        for value in values.iter().copied().chain(random) {
+            if !value.is_finite() || value == 0.0 {
+                continue;
+            }
+            let (significand, exponent) = decimal_expansion(value, exact_precision(value));
+            assert_eq!(
+                (significand.trim_end_matches('0').to_string(), exponent),
+                reference_expansion(value),
+                "{:e}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn ties_round_to_even() {
+        assert_eq!(float_form(0.125, 2, Rounding::NearestEven), "+1.2e-1");
+        assert_eq!(float_form(0.375, 2, Rounding::NearestEven), "+3.8e-1");
+        assert_eq!(float_form(0.0625, 2, Rounding::NearestEven), "+6.2e-2");
+        assert_eq!(float_form(1.5, 1, Rounding::NearestEven), "+2.e+");
+        assert_eq!(float_form(2.5, 1, Rounding::NearestEven), "+2.e+");
+        assert_eq!(float_form(3.5, 1, Rounding::NearestEven), "+4.e+");
+        assert_eq!(float_form(-2.5, 1, Rounding::NearestEven), "-2.e+");
+        assert_eq!(float_form(-3.5, 1, Rounding::NearestEven), "-4.e+");
+        assert_eq!(float_form(9.5, 1, Rounding::NearestEven), "+1.e+1");
+        assert_eq!(
+            float_form(1234567.5, 7, Rounding::NearestEven),
+            "+1.234568e+6"
+        );
+        assert_eq!(
+            float_form(1234568.5, 7, Rounding::NearestEven),
+            "+1.234568e+6"
+        );
+        // The first value after a tie is no longer a tie
+        assert_eq!(
+            float_form(
+                f64::from_bits(2.5_f64.to_bits() + 1),
+                1,
+                Rounding::NearestEven
+            ),
+            "+3.e+"
+        );
+        assert_eq!(integer_form(125, 2, Rounding::NearestEven), "+1.2e+2");
+        assert_eq!(integer_form(135, 2, Rounding::NearestEven), "+1.4e+2");
+        assert_eq!(integer_form(1251, 2, Rounding::NearestEven), "+1.3e+3");
+    }
+
+    #[test]
+    fn binary_representation_is_exact() {
+        // 2.675 is stored as 2.67499999999999982236431605997495353221893310546875
+        assert_eq!(float_form(2.675, 3, Rounding::NearestEven), "+2.67e+");
+        assert_eq!(
+            float_form(0.1, 15, Rounding::NearestEven),
+            "+1.e-1".to_string()
+        );
+        assert_eq!(
+            float_form(0.1, 17, Rounding::NearestEven),
+            "+1.0000000000000001e-1"
+        );
+    }
+
+    #[test]
+    fn toward_zero_truncates_ties() {
+        assert_eq!(float_form(2.5, 1, Rounding::TowardZero), "+2.e+");
+        assert_eq!(float_form(3.5, 1, Rounding::TowardZero), "+3.e+");
+        assert_eq!(float_form(-3.5, 1, Rounding::TowardZero), "-3.e+");
+        assert_eq!(float_form(9.5, 1, Rounding::TowardZero), "+9.e+");
+        assert_eq!(
+            float_form(1234567.5, 7, Rounding::TowardZero),
+            "+1.234567e+6"
+        );
+    }
+
+    #[test]
+    fn toward_zero_truncates() {
+        assert_eq!(
+            float_form(1.99999999, 7, Rounding::TowardZero),
+            "+1.999999e+"
+        );
+        assert_eq!(
+            float_form(-1.99999999, 7, Rounding::TowardZero),
+            "-1.999999e+"
+        );
+        assert_eq!(
+            integer_form(-99999999, 7, Rounding::TowardZero),
+            "-9.999999e+7"
+        );
+        // 10.00001 is stored as 10.0000099999999996214..., which reads as 10.00001 when
+        // correctly rounded to 17 digits
+        assert_eq!(float_form(10.00001, 7, Rounding::TowardZero), "+1.e+1");
+        assert_eq!(float_form(1.0, 7, Rounding::TowardZero), "+1.e+");
+    }
+
+    #[test]
+    fn carries_into_exponent() {
+        assert_eq!(float_form(9.9999999, 7, Rounding::NearestEven), "+1.e+1");
+        assert_eq!(integer_form(99999999, 7, Rounding::NearestEven), "+1.e+8");
+        assert_eq!(
+            integer_form(i64::MIN, 7, Rounding::NearestEven),
+            "-9.223372e+18"
+        );
+    }
+
+    #[test]
+    fn scaled_values() {
+        assert_eq!(
+            scaled_form(false, "12345", 2, 7, Rounding::NearestEven),
+            "+1.2345e+2"
+        );
+        assert_eq!(
+            scaled_form(true, "000", 2, 7, Rounding::NearestEven),
+            "+0.e+"
+        );
+        assert_eq!(
+            scaled_form(false, "5", 3, 7, Rounding::NearestEven),
+            "+5.e-3"
+        );
+    }
+}
@@ -0,0 +1,1037 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, Date32Array, Float64Array, StringArray, TimestampMillisecondArray},
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::Result as ArrowResult,
+    record_batch::{RecordBatch, RecordBatchReader},
+};
+use flate2::bufread::ZlibDecoder;
+
+use crate::{
+    binary::{invalid, read_bytes, unix_days, unix_milliseconds, ByteOrder, Encoding},
+    error::Result,
+};
+
+/// Days and milliseconds between the SPSS epoch of 1582-10-14 and the Unix epoch
+const EPOCH_DAYS: i32 = 141_428;
+const EPOCH_MILLISECONDS: i64 = 12_219_379_200_000;
+
+/// Bytes of a segment of a very long string that hold its value
+const SEGMENT_BYTES: usize = 252;
+
+/// Print formats whose values are converted to Arrow temporal types
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DateFormat {
+    /// `DATE`, `ADATE`, `EDATE`, `SDATE` and `JDATE`: seconds since 1582-10-14 at midnight
+    Date,
+    /// `DATETIME` and `YMDHMS`: seconds since 1582-10-14 00:00:00
+    DateTime,
+}
+
+impl DateFormat {
+    fn from_format(format_type: u8) -> Option<Self> {
+        match format_type {
+            20 | 23 | 24 | 38 | 39 => Some(DateFormat::Date),
+            22 | 41 => Some(DateFormat::DateTime),
+            _ => None,
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            DateFormat::Date => DataType::Date32,
+            DateFormat::DateTime => DataType::Timestamp(TimeUnit::Millisecond, None),
+        }
+    }
+}
+
+/// User missing values of a variable
+#[derive(Clone, Debug, Default)]
+struct MissingValues {
+    numbers: Vec<f64>,
+    range: Option<(f64, f64)>,
+    /// Strings without their trailing spaces
+    strings: Vec<Vec<u8>>,
+}
+
+impl MissingValues {
+    fn contains_number(&self, x: f64) -> bool {
+        self.numbers.contains(&x)
+            || matches!(self.range, Some((low, high)) if low <= x && x <= high)
+    }
+}
+
+/// A variable record of the dictionary, before very long strings are joined
+struct VariableRecord {
+    name: Vec<u8>,
+    /// Width of a string, or 0 for a number
+    width: usize,
+    format_type: u8,
+    missing: MissingValues,
+    /// Index of the first 8-byte segment of the variable within a case
+    segment: usize,
+}
+
+struct Variable {
+    /// Date format of a number, unused by strings
+    date_format: Option<DateFormat>,
+    string: bool,
+    /// Position and length of each part of the value within a case
+    parts: Vec<(usize, usize)>,
+    missing: MissingValues,
+}
+
+/// Case data, which compressed files store as bytecode and zsav files also compress as a
+/// series of zlib blocks
+enum Source<R: BufRead> {
+    Plain(R),
+    Zlib {
+        decoder: Option<ZlibDecoder<R>>,
+        position: u64,
+        end: u64,
+    },
+}
+
+impl<R: BufRead> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::Plain(reader) => reader.read(buf),
+            Source::Zlib {
+                decoder,
+                position,
+                end,
+            } => loop {
+                let read = match decoder {
+                    Some(decoder) => decoder.read(buf)?,
+                    None => return Ok(0),
+                };
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+                // Each block is a zlib stream, and the next one starts where this one ended
+                let finished = decoder.take().expect("The decoder was just read");
+                if finished.total_in() == 0 {
+                    return Ok(0);
+                }
+                *position += finished.total_in();
+                if *position < *end {
+                    *decoder = Some(ZlibDecoder::new(finished.into_inner()));
+                }
+            },
+        }
+    }
+}
+
+/// Reader of the 8-byte segments that make up the cases of a file
+struct Segments<R: BufRead> {
+    source: Source<R>,
+    /// Bias of compressed numbers, when the cases are compressed
+    bias: Option<f64>,
+    byte_order: ByteOrder,
+    system_missing: f64,
+    commands: [u8; 8],
+    command: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> Segments<R> {
+    /// Read the next segment, or `None` at the end of the data
+    fn next_segment(&mut self) -> io::Result<Option<[u8; 8]>> {
+        let bias = match self.bias {
+            Some(bias) => bias,
+            None => return self.raw_segment(),
+        };
+        while !self.finished {
+            if self.command == self.commands.len() {
+                match self.raw_segment()? {
+                    Some(commands) => self.commands = commands,
+                    None => break,
+                }
+                self.command = 0;
+            }
+            let code = self.commands[self.command];
+            self.command += 1;
+            match code {
+                0 => {}
+                1..=251 => return Ok(Some(self.byte_order.f64_bytes(f64::from(code) - bias))),
+                252 => break,
+                253 => {
+                    return self
+                        .raw_segment()?
+                        .map(Some)
+                        .ok_or_else(|| invalid("Truncated compressed data".to_string()))
+                }
+                254 => return Ok(Some([b' '; 8])),
+                _ => return Ok(Some(self.byte_order.f64_bytes(self.system_missing))),
+            }
+        }
+        self.finished = true;
+        Ok(None)
+    }
+
+    fn raw_segment(&mut self) -> io::Result<Option<[u8; 8]>> {
+        let mut segment = [0; 8];
+        let mut read = 0;
+        while read < segment.len() {
+            match self.source.read(&mut segment[read..]) {
+                Ok(0) => break,
+                Ok(len) => read += len,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        match read {
+            0 => Ok(None),
+            8 => Ok(Some(segment)),
+            _ => Err(invalid("Truncated case data".to_string())),
+        }
+    }
+}
+
+/// Reader of SPSS system files, `.sav` and compressed `.zsav`, that yields batches of cases
+///
+/// Numeric variables are read as Float64 and string variables as Utf8 without their trailing
+/// spaces. The system missing value and each variable's user missing values, whether discrete
+/// values or a range, are read as nulls.
+///
+/// Variables printed with a `DATE`, `ADATE`, `EDATE`, `SDATE` or `JDATE` format are read as
+/// Date32, and variables printed with `DATETIME` or `YMDHMS` as millisecond Timestamps without
+/// a time zone. Other date and time formats, such as `MOYR` or `TIME`, are read as numbers.
+/// Value labels are ignored.
+///
+/// Strings are decoded by the file's declared character encoding, which may be UTF-8,
+/// Windows-1252, Latin-1 or ASCII. Files in any other encoding are not read.
+pub struct SpssReader<R: BufRead> {
+    segments: Segments<R>,
+    schema: SchemaRef,
+    variables: Vec<Variable>,
+    byte_order: ByteOrder,
+    encoding: Encoding,
+    /// Segments of each case
+    case_len: usize,
+    /// Cases left to read, when the header records the number of cases
+    remaining: Option<u64>,
+    batch_size: usize,
+}
+
+/// Read an SPSS `.sav` or `.zsav` file in batches of `batch_size` cases
+pub fn read_spss_data(file_path: String, batch_size: usize) -> Result<SpssReader<BufReader<File>>> {
+    SpssReader::try_new(BufReader::new(File::open(file_path)?), batch_size)
+}
+
+impl<R: BufRead> SpssReader<R> {
+    /// Read the dictionary of a file, leaving the reader at its cases
+    pub fn try_new(mut reader: R, batch_size: usize) -> Result<Self> {
+        let header = read_bytes(&mut reader, 176)?;
+        let zlib = match &header[..4] {
+            b"$FL2" => false,
+            b"$FL3" => true,
+            _ => return Err(invalid("Not an SPSS system file".to_string()).into()),
+        };
+        let byte_order = match ByteOrder::Little.i32(&header[64..]) {
+            2 | 3 => ByteOrder::Little,
+            _ => ByteOrder::Big,
+        };
+        let compression = byte_order.i32(&header[72..]);
+        let cases = byte_order.i32(&header[80..]);
+        let bias = byte_order.f64(&header[84..]);
+        if !(0..=2).contains(&compression) || (compression == 2) != zlib {
+            return Err(invalid(format!("Unknown compression {}", compression)).into());
+        }
+
+        let mut records = Vec::new();
+        let mut case_len = 0;
+        let mut extensions = HashMap::new();
+        loop {
+            let record_type = byte_order.i32(&read_bytes(&mut reader, 4)?);
+            match record_type {
+                2 => {
+                    if let Some(record) = read_variable(&mut reader, byte_order, case_len)? {
+                        records.push(record);
+                    }
+                    case_len += 1;
+                }
+                3 => {
+                    let count = byte_order.i32(&read_bytes(&mut reader, 4)?);
+                    for _ in 0..count {
+                        let label = read_bytes(&mut reader, 9)?;
+                        // Labels are padded so that they and their length fill multiples of 8 bytes
+                        read_bytes(&mut reader, (label[8] as usize + 1).div_ceil(8) * 8 - 1)?;
+                    }
+                    let variables = read_bytes(&mut reader, 8)?;
+                    if byte_order.i32(&variables) != 4 {
+                        return Err(invalid("Value labels without variables".to_string()).into());
+                    }
+                    read_bytes(&mut reader, 4 * len(byte_order.i32(&variables[4..]))?)?;
+                }
+                6 => {
+                    let lines = byte_order.i32(&read_bytes(&mut reader, 4)?);
+                    read_bytes(&mut reader, 80 * len(lines)?)?;
+                }
+                7 => {
+                    let extension = read_bytes(&mut reader, 12)?;
+                    let size = len(byte_order.i32(&extension[4..]))?;
+                    let count = len(byte_order.i32(&extension[8..]))?;
+                    extensions.insert(
+                        byte_order.i32(&extension),
+                        read_bytes(&mut reader, size * count)?,
+                    );
+                }
+                999 => {
+                    read_bytes(&mut reader, 4)?;
+                    break;
+                }
+                _ => return Err(invalid(format!("Unknown record type {}", record_type)).into()),
+            }
+        }
+
+        let encoding = encoding(&extensions, byte_order)?;
+        let system_missing = extensions
+            .get(&4)
+            .map(|machine| byte_order.f64(machine))
+            .unwrap_or(-f64::MAX);
+        let long_names = match extensions.get(&13) {
+            Some(names) => key_values(&encoding.decode(names)?),
+            None => HashMap::new(),
+        };
+        let very_long_strings = match extensions.get(&14) {
+            Some(widths) => key_values(&encoding.decode(widths)?),
+            None => HashMap::new(),
+        };
+        if let Some(missing) = extensions.get(&22) {
+            long_string_missing_values(missing, byte_order, &mut records)?;
+        }
+
+        let (fields, variables): (Vec<Field>, Vec<Variable>) =
+            join_records(records, encoding, &long_names, &very_long_strings)?
+                .into_iter()
+                .unzip();
+
+        let source = if zlib {
+            let zheader = read_bytes(&mut reader, 24)?;
+            let position = byte_order.i64(&zheader) as u64 + 24;
+            let end = byte_order.i64(&zheader[8..]) as u64;
+            Source::Zlib {
+                decoder: Some(ZlibDecoder::new(reader)).filter(|_| position < end),
+                position,
+                end,
+            }
+        } else {
+            Source::Plain(reader)
+        };
+        Ok(SpssReader {
+            segments: Segments {
+                source,
+                bias: Some(bias).filter(|_| compression != 0),
+                byte_order,
+                system_missing,
+                commands: [0; 8],
+                command: 8,
+                finished: false,
+            },
+            schema: Arc::new(Schema::new(fields)),
+            variables,
+            byte_order,
+            encoding,
+            case_len,
+            remaining: u64::try_from(cases).ok(),
+            batch_size,
+        })
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Read up to `batch_size` cases, returning `None` when there are none left
+    fn read_batch(&mut self) -> io::Result<Option<RecordBatch>> {
+        let mut cases = Vec::with_capacity(self.batch_size * self.case_len * 8);
+        let mut rows = 0;
+        while rows < self.batch_size && self.remaining != Some(0) {
+            if !self.read_case(&mut cases)? {
+                self.remaining = Some(0);
+                break;
+            }
+            rows += 1;
+            self.remaining = self.remaining.map(|x| x - 1);
+        }
+        if rows == 0 {
+            return Ok(None);
+        }
+        let columns = self
+            .variables
+            .iter()
+            .map(|variable| self.column(variable, &cases))
+            .collect::<io::Result<Vec<ArrayRef>>>()?;
+        RecordBatch::try_new(self.schema.clone(), columns)
+            .map(Some)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Append the segments of the next case, returning `false` at the end of the data
+    fn read_case(&mut self, cases: &mut Vec<u8>) -> io::Result<bool> {
+        for index in 0..self.case_len {
+            match self.segments.next_segment()? {
+                Some(segment) => cases.extend(segment),
+                None if index == 0 => return Ok(false),
+                None => return Err(invalid("Truncated case".to_string())),
+            }
+        }
+        Ok(true)
+    }
+
+    fn column(&self, variable: &Variable, cases: &[u8]) -> io::Result<ArrayRef> {
+        let cases = cases.chunks(self.case_len * 8);
+        if variable.string {
+            let values = cases
+                .map(|case| {
+                    let value = variable
+                        .parts
+                        .iter()
+                        .flat_map(|(offset, len)| &case[*offset..*offset + *len])
+                        .copied()
+                        .collect::<Vec<u8>>();
+                    let value = trim_end(&value);
+                    if variable.missing.strings.iter().any(|x| x == value) {
+                        Ok(None)
+                    } else {
+                        self.encoding.decode(value).map(Some)
+                    }
+                })
+                .collect::<io::Result<Vec<Option<String>>>>()?;
+            return Ok(Arc::new(values.into_iter().collect::<StringArray>()));
+        }
+        let offset = variable.parts[0].0;
+        let numbers = cases.map(|case| {
+            Some(self.byte_order.f64(&case[offset..])).filter(|x| {
+                *x != self.segments.system_missing && !variable.missing.contains_number(*x)
+            })
+        });
+        Ok(match variable.date_format {
+            Some(DateFormat::Date) => Arc::new(Date32Array::from(
+                numbers
+                    .map(|x| x.map(|x| unix_days(x / 86_400.0, EPOCH_DAYS)).transpose())
+                    .collect::<io::Result<Vec<_>>>()?,
+            )),
+            Some(DateFormat::DateTime) => Arc::new(TimestampMillisecondArray::from_opt_vec(
+                numbers
+                    .map(|x| {
+                        x.map(|x| unix_milliseconds(x * 1_000.0, EPOCH_MILLISECONDS))
+                            .transpose()
+                    })
+                    .collect::<io::Result<Vec<_>>>()?,
+                None,
+            )),
+            None => Arc::new(Float64Array::from(numbers.collect::<Vec<_>>())),
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for SpssReader<R> {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_batch() {
+            Ok(batch) => batch.map(Ok),
+            Err(e) => {
+                self.remaining = Some(0);
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> RecordBatchReader for SpssReader<R> {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Convert a length read from the file, which must not be negative
+fn len(x: i32) -> io::Result<usize> {
+    usize::try_from(x).map_err(|_| invalid(format!("Invalid length {}", x)))
+}
+
+fn trim_end(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().rposition(|x| *x != b' ').map_or(0, |x| x + 1);
+    &bytes[..end]
+}
+
+/// Read a variable record, or `None` for the records that continue strings wider than 8 bytes
+fn read_variable<R: Read>(
+    reader: &mut R,
+    byte_order: ByteOrder,
+    segment: usize,
+) -> io::Result<Option<VariableRecord>> {
+    let record = read_bytes(reader, 28)?;
+    let width = byte_order.i32(&record);
+    let has_label = byte_order.i32(&record[4..]);
+    let missing_count = byte_order.i32(&record[8..]);
+    if has_label != 0 {
+        let label_len = len(byte_order.i32(&read_bytes(reader, 4)?))?;
+        read_bytes(reader, label_len.div_ceil(4) * 4)?;
+    }
+    if !(-3..=3).contains(&missing_count) || missing_count == -1 {
+        return Err(invalid(format!(
+            "Invalid missing value count {}",
+            missing_count
+        )));
+    }
+    let missing = read_bytes(reader, 8 * missing_count.unsigned_abs() as usize)?;
+    if width < 0 {
+        return Ok(None);
+    }
+    let missing = missing.chunks(8);
+    let missing = if width > 0 {
+        MissingValues {
+            strings: missing.map(|x| trim_end(x).to_vec()).collect(),
+            ..MissingValues::default()
+        }
+    } else {
+        let mut numbers = missing.map(|x| byte_order.f64(x)).collect::<Vec<f64>>();
+        // A count of -2 is a range, and -3 is a range followed by a discrete value
+        let range = if missing_count < 0 {
+            Some((numbers.remove(0), numbers.remove(0)))
+        } else {
+            None
+        };
+        MissingValues {
+            numbers,
+            range,
+            strings: Vec::new(),
+        }
+    };
+    Ok(Some(VariableRecord {
+        name: trim_end(&record[20..]).to_vec(),
+        width: width as usize,
+        format_type: (byte_order.i32(&record[12..]) >> 16) as u8,
+        missing,
+        segment,
+    }))
+}
+
+/// Encoding of the strings of a file, from its encoding record or else the code page of its
+/// machine integer record
+///
+/// Files that declare neither are read as Latin-1, while encodings that are declared but not
+/// supported are errors rather than being read as something else.
+fn encoding(extensions: &HashMap<i32, Vec<u8>>, byte_order: ByteOrder) -> io::Result<Encoding> {
+    let machine = extensions.get(&3).filter(|machine| machine.len() >= 32);
+    match (extensions.get(&20), machine) {
+        (Some(name), _) => {
+            let name = String::from_utf8_lossy(name)
+                .trim_end_matches('\0')
+                .to_ascii_uppercase();
+            match name.as_str() {
+                "UTF-8" | "UTF8" => Ok(Encoding::Utf8),
+                "WINDOWS-1252" | "CP1252" => Ok(Encoding::Windows1252),
+                "ISO-8859-1" | "ISO_8859-1" | "ISO8859-1" | "LATIN1" => Ok(Encoding::Latin1),
+                "US-ASCII" | "ASCII" => Ok(Encoding::Ascii),
+                _ => Err(invalid(format!("Unsupported character encoding {}", name))),
+            }
+        }
+        (None, Some(machine)) => match byte_order.i32(&machine[28..]) {
+            65001 => Ok(Encoding::Utf8),
+            1252 => Ok(Encoding::Windows1252),
+            28591 => Ok(Encoding::Latin1),
+            // 2 is the 7-bit ASCII of older files
+            2 | 20127 => Ok(Encoding::Ascii),
+            code => Err(invalid(format!("Unsupported character code {}", code))),
+        },
+        (None, None) => Ok(Encoding::Latin1),
+    }
+}
+
+/// Join the segments of very long strings into single variables, each with its long name
+fn join_records(
+    records: Vec<VariableRecord>,
+    encoding: Encoding,
+    long_names: &HashMap<String, String>,
+    very_long_strings: &HashMap<String, String>,
+) -> io::Result<Vec<(Field, Variable)>> {
+    let mut variables = Vec::with_capacity(records.len());
+    let mut records = records.into_iter();
+    while let Some(record) = records.next() {
+        let short_name = encoding.decode(&record.name)?;
+        let width = match very_long_strings.get(&short_name) {
+            Some(width) => width
+                .trim_end_matches('\0')
+                .parse::<usize>()
+                .map_err(|_| invalid(format!("Invalid width of {}", short_name)))?,
+            None => record.width,
+        };
+        let variable = if width > record.width {
+            // Every segment but the last holds 252 bytes of the string
+            let segments = width.div_ceil(SEGMENT_BYTES);
+            let mut parts = vec![(8 * record.segment, SEGMENT_BYTES)];
+            for index in 1..segments {
+                let segment = records
+                    .next()
+                    .ok_or_else(|| invalid(format!("Missing segment of {}", short_name)))?;
+                let len = if index + 1 < segments {
+                    SEGMENT_BYTES
+                } else {
+                    segment.width
+                };
+                parts.push((8 * segment.segment, len));
+            }
+            Variable {
+                date_format: None,
+                string: true,
+                parts,
+                missing: record.missing,
+            }
+        } else {
+            Variable {
+                date_format: DateFormat::from_format(record.format_type)
+                    .filter(|_| record.width == 0),
+                string: record.width > 0,
+                parts: vec![(8 * record.segment, record.width.max(8))],
+                missing: record.missing,
+            }
+        };
+        let data_type = match (variable.string, variable.date_format) {
+            (true, _) => DataType::Utf8,
+            (false, Some(date_format)) => date_format.data_type(),
+            (false, None) => DataType::Float64,
+        };
+        let name = long_names.get(&short_name).unwrap_or(&short_name);
+        variables.push((Field::new(name, data_type, true), variable));
+    }
+    Ok(variables)
+}
+
+/// Parse `KEY=value` pairs separated by tabs, as used by long names and very long strings
+fn key_values(text: &str) -> HashMap<String, String> {
+    text.split('\t')
+        .filter_map(|x| x.trim_matches('\0').split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Read the missing values of strings wider than 8 bytes, which follow the dictionary
+fn long_string_missing_values(
+    bytes: &[u8],
+    byte_order: ByteOrder,
+    records: &mut [VariableRecord],
+) -> io::Result<()> {
+    let mut bytes = bytes;
+    let mut take = |len: usize| {
+        if bytes.len() < len {
+            return Err(invalid("Truncated long string missing values".to_string()));
+        }
+        let (taken, rest) = bytes.split_at(len);
+        bytes = rest;
+        Ok(taken)
+    };
+    while let Ok(name_len) = take(4) {
+        let name = take(len(byte_order.i32(name_len))?)?;
+        let count = take(1)?[0] as usize;
+        let value_len = len(byte_order.i32(take(4)?))?;
+        let values = (0..count)
+            .map(|_| take(value_len).map(|x| trim_end(x).to_vec()))
+            .collect::<io::Result<Vec<_>>>()?;
+        if let Some(record) = records.iter_mut().find(|x| x.name == trim_end(name)) {
+            record.missing.strings = values;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::TryInto,
+        io::{Cursor, Write},
+    };
+
+    use arrow::array::Array;
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use super::*;
+
+    const SYSTEM_MISSING: f64 = -f64::MAX;
+
+    struct TestVariable {
+        name: &'static str,
+        width: i32,
+        format: i32,
+        missing_count: i32,
+        missing: Vec<[u8; 8]>,
+    }
+
+    fn numeric(name: &'static str) -> TestVariable {
+        TestVariable {
+            name,
+            width: 0,
+            format: 5 << 16 | 8 << 8 | 2,
+            missing_count: 0,
+            missing: Vec::new(),
+        }
+    }
+
+    fn string(name: &'static str, width: i32) -> TestVariable {
+        TestVariable {
+            name,
+            width,
+            format: 1 << 16 | width << 8,
+            missing_count: 0,
+            missing: Vec::new(),
+        }
+    }
+
+    fn text(value: &str, len: usize) -> Vec<u8> {
+        let mut text = value.as_bytes().to_vec();
+        text.resize(len, b' ');
+        text
+    }
+
+    fn segment(x: f64) -> [u8; 8] {
+        x.to_le_bytes()
+    }
+
+    /// Write a little-endian file of the given variables and extension records
+    ///
+    /// The compression is 0 for plain cases, 1 for bytecode and 2 for zlib blocks of bytecode.
+    fn sav(
+        compression: i32,
+        variables: &[TestVariable],
+        extensions: &[(i32, Vec<u8>)],
+        segments: &[[u8; 8]],
+    ) -> Vec<u8> {
+        let mut sav = if compression == 2 { b"$FL3" } else { b"$FL2" }.to_vec();
+        sav.extend(text("@(#) SPSS DATA FILE", 60));
+        sav.extend(2i32.to_le_bytes());
+        sav.extend((-1i32).to_le_bytes());
+        sav.extend(compression.to_le_bytes());
+        sav.extend(0i32.to_le_bytes());
+        sav.extend((-1i32).to_le_bytes());
+        sav.extend(100f64.to_le_bytes());
+        sav.extend([b' '; 9 + 8 + 64 + 3]);
+        for variable in variables {
+            sav.extend(2i32.to_le_bytes());
+            sav.extend(variable.width.to_le_bytes());
+            sav.extend(1i32.to_le_bytes());
+            sav.extend(variable.missing_count.to_le_bytes());
+            sav.extend(variable.format.to_le_bytes());
+            sav.extend(variable.format.to_le_bytes());
+            sav.extend(text(variable.name, 8));
+            sav.extend(5i32.to_le_bytes());
+            sav.extend(text("Label", 8));
+            sav.extend(variable.missing.concat());
+            for _ in 1..(variable.width + 7) / 8 {
+                sav.extend(2i32.to_le_bytes());
+                sav.extend((-1i32).to_le_bytes());
+                sav.extend([0; 16]);
+                sav.extend(text("", 8));
+            }
+        }
+        // A value label of the first variable and a document
+        sav.extend(3i32.to_le_bytes());
+        sav.extend(1i32.to_le_bytes());
+        sav.extend(1f64.to_le_bytes());
+        sav.push(3);
+        sav.extend(text("Yes", 7));
+        sav.extend(4i32.to_le_bytes());
+        sav.extend(1i32.to_le_bytes());
+        sav.extend(1i32.to_le_bytes());
+        sav.extend(6i32.to_le_bytes());
+        sav.extend(1i32.to_le_bytes());
+        sav.extend(text("A document", 80));
+        for (subtype, data) in extensions {
+            sav.extend(7i32.to_le_bytes());
+            sav.extend(subtype.to_le_bytes());
+            sav.extend(1i32.to_le_bytes());
+            sav.extend((data.len() as i32).to_le_bytes());
+            sav.extend(data);
+        }
+        sav.extend(999i32.to_le_bytes());
+        sav.extend(0i32.to_le_bytes());
+        match compression {
+            0 => sav.extend(segments.concat()),
+            1 => sav.extend(bytecode(segments)),
+            _ => {
+                let bytecode = bytecode(segments);
+                let zheader = sav.len() as i64;
+                let blocks = bytecode
+                    .chunks(40)
+                    .map(|block| {
+                        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                        encoder.write_all(block).unwrap();
+                        encoder.finish().unwrap()
+                    })
+                    .collect::<Vec<Vec<u8>>>()
+                    .concat();
+                sav.extend(zheader.to_le_bytes());
+                sav.extend((zheader + 24 + blocks.len() as i64).to_le_bytes());
+                sav.extend(48i64.to_le_bytes());
+                sav.extend(blocks);
+                // The trailer, whose block index the reader does not need
+                sav.extend([0; 48]);
+            }
+        }
+        sav
+    }
+
+    fn bytecode(segments: &[[u8; 8]]) -> Vec<u8> {
+        let mut bytecode = Vec::new();
+        for group in segments.chunks(8) {
+            let mut commands = [0; 8];
+            let mut data: Vec<u8> = Vec::new();
+            for (command, segment) in commands.iter_mut().zip(group) {
+                let x = f64::from_le_bytes(*segment);
+                *command = if segment == &[b' '; 8] {
+                    254
+                } else if x == SYSTEM_MISSING {
+                    255
+                } else if x.fract() == 0.0 && (-99.0..=151.0).contains(&x) {
+                    (x + 100.0) as u8
+                } else {
+                    data.extend(segment);
+                    253
+                };
+            }
+            bytecode.extend(commands);
+            bytecode.extend(data);
+        }
+        bytecode
+    }
+
+    fn read(sav: Vec<u8>, batch_size: usize) -> Vec<RecordBatch> {
+        SpssReader::try_new(Cursor::new(sav), batch_size)
+            .unwrap()
+            .collect::<ArrowResult<Vec<RecordBatch>>>()
+            .unwrap()
+    }
+
+    fn column<T: 'static>(batch: &RecordBatch, index: usize) -> &T {
+        batch.column(index).as_any().downcast_ref::<T>().unwrap()
+    }
+
+    fn variables() -> Vec<TestVariable> {
+        vec![
+            TestVariable {
+                missing_count: 1,
+                missing: vec![segment(9.0)],
+                ..numeric("X")
+            },
+            TestVariable {
+                missing_count: -3,
+                missing: vec![segment(90.0), segment(99.0), segment(-1.0)],
+                ..numeric("R")
+            },
+            TestVariable {
+                missing_count: 1,
+                missing: vec![text("NA", 8).try_into().unwrap()],
+                ..string("S", 3)
+            },
+            string("L", 12),
+        ]
+    }
+
+    fn cases() -> Vec<[u8; 8]> {
+        let mut segments = Vec::new();
+        let cases = [
+            (1.5, 5.0, "ab", "hello world!"),
+            (SYSTEM_MISSING, 95.0, "NA", "hi"),
+            (9.0, 99.5, "", ""),
+            (0.25, -1.0, "abc", "spaces  "),
+        ];
+        for (x, r, s, l) in cases {
+            segments.push(segment(x));
+            segments.push(segment(r));
+            segments.push(text(s, 8).try_into().unwrap());
+            for part in text(l, 16).chunks(8) {
+                segments.push(part.try_into().unwrap());
+            }
+        }
+        segments
+    }
+
+    #[test]
+    fn numbers_strings_and_missing_values() {
+        // Missing values of the long string L
+        let mut long_string_missing = 1i32.to_le_bytes().to_vec();
+        long_string_missing.extend(b"L\x01");
+        long_string_missing.extend(8i32.to_le_bytes());
+        long_string_missing.extend(text("hi", 8));
+        let sav = sav(0, &variables(), &[(22, long_string_missing)], &cases());
+        let reader = SpssReader::try_new(Cursor::new(sav), 10).unwrap();
+        let data_types = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|x| x.data_type().clone())
+            .collect::<Vec<DataType>>();
+        assert_eq!(
+            data_types,
+            vec![
+                DataType::Float64,
+                DataType::Float64,
+                DataType::Utf8,
+                DataType::Utf8
+            ]
+        );
+        let batches = reader.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        let x = column::<Float64Array>(&batches[0], 0);
+        assert_eq!(x.value(0), 1.5);
+        assert!(x.is_null(1));
+        assert!(x.is_null(2));
+        let r = column::<Float64Array>(&batches[0], 1);
+        assert!(r.is_null(1));
+        assert_eq!(r.value(2), 99.5);
+        assert!(r.is_null(3));
+        let s = column::<StringArray>(&batches[0], 2);
+        assert_eq!(s.value(0), "ab");
+        assert!(s.is_null(1));
+        assert_eq!(s.value(2), "");
+        let l = column::<StringArray>(&batches[0], 3);
+        assert_eq!(l.value(0), "hello world!");
+        assert!(l.is_null(1));
+        assert_eq!(l.value(3), "spaces");
+    }
+
+    #[test]
+    fn compressed_cases() {
+        let plain = read(sav(0, &variables(), &[], &cases()), 10);
+        for compression in 1..=2 {
+            let batches = read(sav(compression, &variables(), &[], &cases()), 3);
+            assert_eq!(
+                batches.iter().map(|x| x.num_rows()).collect::<Vec<_>>(),
+                vec![3, 1]
+            );
+            for (batch, offset) in batches.iter().zip([0, 3]) {
+                let expected = plain[0].slice(offset, batch.num_rows());
+                for index in 0..4 {
+                    assert_eq!(
+                        format!("{:?}", batch.column(index)),
+                        format!("{:?}", expected.column(index))
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn long_names_and_very_long_strings() {
+        let mut variables = vec![string("NOTES", 255), string("NOTES0", 48)];
+        variables[0].format = 1 << 16 | 255 << 8;
+        // The second segment starts within a two-byte character
+        let notes = format!("x{}x", "é".repeat(149));
+        let mut case = notes.as_bytes()[..252].to_vec();
+        case.resize(256, b' ');
+        case.extend(&notes.as_bytes()[252..]);
+        let segments = case
+            .chunks(8)
+            .map(|x| x.try_into().unwrap())
+            .collect::<Vec<[u8; 8]>>();
+        let extensions = [
+            (13, b"NOTES=Interview_notes".to_vec()),
+            (14, b"NOTES=00300\0\t".to_vec()),
+            (20, b"UTF-8".to_vec()),
+        ];
+        let batches = read(sav(0, &variables, &extensions, &segments), 10);
+        assert_eq!(batches[0].num_columns(), 1);
+        assert_eq!(batches[0].schema().field(0).name(), "Interview_notes");
+        assert_eq!(column::<StringArray>(&batches[0], 0).value(0), notes);
+    }
+
+    #[test]
+    fn code_pages() {
+        let machine = |code: i32| {
+            let mut machine = vec![0; 28];
+            machine.extend(code.to_le_bytes());
+            machine
+        };
+        let value = [b'\x80', b'\x9f', b'\xe9', b' ', b' ', b' ', b' ', b' '];
+        for (extension, decoded) in [
+            ((20, b"windows-1252".to_vec()), "\u{20ac}\u{178}\u{e9}"),
+            ((3, machine(1252)), "\u{20ac}\u{178}\u{e9}"),
+            ((20, b"ISO-8859-1".to_vec()), "\u{80}\u{9f}\u{e9}"),
+            ((3, machine(28591)), "\u{80}\u{9f}\u{e9}"),
+        ] {
+            let sav = sav(0, &[string("S", 8)], &[extension], &[value]);
+            let batches = read(sav, 10);
+            assert_eq!(column::<StringArray>(&batches[0], 0).value(0), decoded);
+        }
+        for extension in [
+            (20, b"Shift_JIS".to_vec()),
+            (3, machine(932)),
+            (3, machine(20127)),
+        ] {
+            let sav = sav(0, &[string("S", 8)], &[extension], &[value]);
+            let batches = SpssReader::try_new(Cursor::new(sav), 10)
+                .and_then(|reader| Ok(reader.collect::<ArrowResult<Vec<RecordBatch>>>()?));
+            assert!(batches.is_err());
+        }
+    }
+
+    #[test]
+    fn dates() {
+        let variables = [
+            TestVariable {
+                format: 20 << 16 | 11 << 8,
+                ..numeric("D")
+            },
+            TestVariable {
+                format: 22 << 16 | 20 << 8,
+                ..numeric("DT")
+            },
+            TestVariable {
+                format: 21 << 16 | 8 << 8,
+                ..numeric("T")
+            },
+        ];
+        let unix_epoch = EPOCH_MILLISECONDS as f64 / 1_000.0;
+        let segments = [
+            segment(unix_epoch),
+            segment(unix_epoch + 1.5),
+            segment(3_600.0),
+            segment(unix_epoch - 86_400.0),
+            segment(SYSTEM_MISSING),
+            segment(SYSTEM_MISSING),
+        ];
+        let batches = read(sav(0, &variables, &[], &segments), 10);
+        let date = column::<Date32Array>(&batches[0], 0);
+        assert_eq!(date.value(0), 0);
+        assert_eq!(date.value(1), -1);
+        let datetime = column::<TimestampMillisecondArray>(&batches[0], 1);
+        assert_eq!(datetime.value(0), 1_500);
+        assert!(datetime.is_null(1));
+        assert_eq!(column::<Float64Array>(&batches[0], 2).value(0), 3_600.0);
+    }
+
+    #[test]
+    fn extreme_dates() {
+        for (format, value) in [
+            (20 << 16 | 11 << 8, 1e300),
+            (20 << 16 | 11 << 8, f64::INFINITY),
+            (22 << 16 | 20 << 8, 1e300),
+            (22 << 16 | 20 << 8, -9.3e15),
+        ] {
+            let variables = [TestVariable {
+                format,
+                ..numeric("D")
+            }];
+            let sav = sav(0, &variables, &[], &[segment(value)]);
+            let batches = SpssReader::try_new(Cursor::new(sav), 10)
+                .unwrap()
+                .collect::<ArrowResult<Vec<RecordBatch>>>();
+            assert!(batches.is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn not_a_system_file() {
+        let mut sav = sav(0, &variables(), &[], &cases());
+        sav[..4].copy_from_slice(b"$FL9");
+        assert!(SpssReader::try_new(Cursor::new(sav), 10).is_err());
+    }
+}
@@ -0,0 +1,969 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{
+        ArrayRef, Date32Array, Float32Array, Float64Array, Int16Array, Int32Array, Int8Array,
+        StringArray, TimestampMillisecondArray,
+    },
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::Result as ArrowResult,
+    record_batch::{RecordBatch, RecordBatchReader},
+};
+
+use crate::{
+    binary::{invalid, read_bytes, unix_days, unix_milliseconds, ByteOrder, Encoding},
+    error::Result,
+};
+
+/// Largest values that are not missing; the values above them are `.`, `.a`, ..., `.z`
+const MAX_BYTE: i8 = 100;
+const MAX_INT: i16 = 32_740;
+const MAX_LONG: i32 = 2_147_483_620;
+const MAX_FLOAT_BITS: u32 = 0x7eff_ffff;
+const MAX_DOUBLE_BITS: u64 = 0x7fdf_ffff_ffff_ffff;
+
+/// Days and milliseconds between the Stata epoch of 1960-01-01 and the Unix epoch
+const EPOCH_DAYS: i32 = 3_653;
+const EPOCH_MILLISECONDS: i64 = 315_619_200_000;
+
+/// Unix times, in seconds, of the instants that follow each leap second
+///
+/// `%tC` date-times count these seconds, while Arrow timestamps do not.
+const LEAP_SECONDS: [i64; 27] = [
+    78_796_800,
+    94_694_400,
+    126_230_400,
+    157_766_400,
+    189_302_400,
+    220_924_800,
+    252_460_800,
+    283_996_800,
+    315_532_800,
+    362_793_600,
+    394_329_600,
+    425_865_600,
+    489_024_000,
+    567_993_600,
+    631_152_000,
+    662_688_000,
+    709_948_800,
+    741_484_800,
+    773_020_800,
+    820_454_400,
+    867_715_200,
+    915_148_800,
+    1_136_073_600,
+    1_230_768_000,
+    1_341_100_800,
+    1_435_708_800,
+    1_483_228_800,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VariableType {
+    /// Fixed width string of the given number of bytes
+    Str(usize),
+    /// Reference to a long string stored after the data
+    StrL,
+    Byte,
+    Int,
+    Long,
+    Float,
+    Double,
+}
+
+impl VariableType {
+    /// Type codes of releases 114 and 115
+    fn from_old_code(code: u8) -> io::Result<Self> {
+        Ok(match code {
+            1..=244 => VariableType::Str(code as usize),
+            251 => VariableType::Byte,
+            252 => VariableType::Int,
+            253 => VariableType::Long,
+            254 => VariableType::Float,
+            255 => VariableType::Double,
+            _ => return Err(invalid(format!("Unknown variable type {}", code))),
+        })
+    }
+
+    /// Type codes of releases 117 and later
+    fn from_code(code: u16) -> io::Result<Self> {
+        Ok(match code {
+            1..=2045 => VariableType::Str(code as usize),
+            32768 => VariableType::StrL,
+            65526 => VariableType::Double,
+            65527 => VariableType::Float,
+            65528 => VariableType::Long,
+            65529 => VariableType::Int,
+            65530 => VariableType::Byte,
+            _ => return Err(invalid(format!("Unknown variable type {}", code))),
+        })
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            VariableType::Str(width) => *width,
+            VariableType::StrL => 8,
+            VariableType::Byte => 1,
+            VariableType::Int => 2,
+            VariableType::Long => 4,
+            VariableType::Float => 4,
+            VariableType::Double => 8,
+        }
+    }
+
+    /// Read a numeric cell, or `None` when it holds a missing value
+    fn number(&self, byte_order: ByteOrder, cell: &[u8]) -> Option<f64> {
+        match self {
+            VariableType::Byte => Some(cell[0] as i8)
+                .filter(|x| *x <= MAX_BYTE)
+                .map(f64::from),
+            VariableType::Int => Some(byte_order.i16(cell))
+                .filter(|x| *x <= MAX_INT)
+                .map(f64::from),
+            VariableType::Long => Some(byte_order.i32(cell))
+                .filter(|x| *x <= MAX_LONG)
+                .map(f64::from),
+            VariableType::Float => Some(byte_order.f32(cell))
+                .filter(|x| *x <= f32::from_bits(MAX_FLOAT_BITS))
+                .map(f64::from),
+            VariableType::Double => {
+                Some(byte_order.f64(cell)).filter(|x| *x <= f64::from_bits(MAX_DOUBLE_BITS))
+            }
+            VariableType::Str(_) | VariableType::StrL => None,
+        }
+    }
+}
+
+/// Display formats whose values are converted to Arrow temporal types
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DateFormat {
+    /// `%td`, or `%d` in older files: days since 1960-01-01
+    Date,
+    /// `%tc`: milliseconds since 1960-01-01 00:00:00, ignoring leap seconds
+    DateTime,
+    /// `%tC`: milliseconds since 1960-01-01 00:00:00, counting leap seconds
+    LeapDateTime,
+}
+
+impl DateFormat {
+    fn from_format(format: &str) -> Option<Self> {
+        let format = format.strip_prefix('%')?;
+        let format = format.strip_prefix('-').unwrap_or(format);
+        if format.starts_with("td") || format.starts_with('d') {
+            Some(DateFormat::Date)
+        } else if format.starts_with("tc") {
+            Some(DateFormat::DateTime)
+        } else if format.starts_with("tC") {
+            Some(DateFormat::LeapDateTime)
+        } else {
+            None
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            DateFormat::Date => DataType::Date32,
+            DateFormat::DateTime | DateFormat::LeapDateTime => {
+                DataType::Timestamp(TimeUnit::Millisecond, None)
+            }
+        }
+    }
+}
+
+struct Variable {
+    variable_type: VariableType,
+    date_format: Option<DateFormat>,
+    /// Position of the variable within a record
+    offset: usize,
+}
+
+/// Reader of Stata `.dta` files that yields batches of observations
+///
+/// Numeric variables keep their storage type, so `byte`, `int`, `long`, `float` and `double`
+/// are read as Int8, Int16, Int32, Float32 and Float64. The missing values `.` and `.a` to
+/// `.z` are read as nulls, while empty strings, which Stata also treats as missing, are kept.
+///
+/// Variables displayed with a `%td` format are read as Date32, and variables displayed with
+/// `%tc` or `%tC` are read as millisecond Timestamps without a time zone, with the leap seconds
+/// counted by `%tC` removed. Weekly, monthly, quarterly, half-yearly and yearly formats have
+/// no Arrow equivalent and are read as numbers. Value labels are ignored.
+pub struct StataReader<R: Read + Seek> {
+    reader: R,
+    schema: SchemaRef,
+    variables: Vec<Variable>,
+    byte_order: ByteOrder,
+    encoding: Encoding,
+    release: u8,
+    record_len: usize,
+    remaining: u64,
+    batch_size: usize,
+    /// Long strings by variable and observation
+    strls: HashMap<(u64, u64), String>,
+}
+
+/// Read a Stata `.dta` file of release 114 to 119 in batches of `batch_size` observations
+///
+/// Releases 114 and 115 are written by Stata 10 to 12, release 117 by Stata 13 and releases
+/// 118 and 119 by Stata 14 and later. Release 116 was never used for files.
+pub fn read_stata_data(
+    file_path: String,
+    batch_size: usize,
+) -> Result<StataReader<BufReader<File>>> {
+    StataReader::try_new(BufReader::new(File::open(file_path)?), batch_size)
+}
+
+impl<R: Read + Seek> StataReader<R> {
+    pub fn try_new(mut reader: R, batch_size: usize) -> Result<Self> {
+        let first = read_bytes(&mut reader, 1)?[0];
+        reader.seek(SeekFrom::Start(0))?;
+        let mut stata = if first == b'<' {
+            StataReader::read_tagged(reader, batch_size)?
+        } else {
+            StataReader::read_old(reader, batch_size)?
+        };
+        let mut offset = 0;
+        for variable in &mut stata.variables {
+            variable.offset = offset;
+            offset += variable.variable_type.width();
+        }
+        stata.record_len = offset;
+        Ok(stata)
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Read the header and descriptors of releases 114 and 115, leaving the reader at the data
+    fn read_old(mut reader: R, batch_size: usize) -> Result<Self> {
+        let header = read_bytes(&mut reader, 109)?;
+        let release = header[0];
+        if !(114..=115).contains(&release) {
+            return Err(invalid(format!("Unsupported Stata release {}", release)).into());
+        }
+        let byte_order = match header[1] {
+            1 => ByteOrder::Big,
+            2 => ByteOrder::Little,
+            x => return Err(invalid(format!("Unknown byte order {}", x)).into()),
+        };
+        let count = byte_order.u16(&header[4..]) as usize;
+        let observations = u64::from(byte_order.u32(&header[6..]));
+        let types = read_bytes(&mut reader, count)?
+            .into_iter()
+            .map(VariableType::from_old_code)
+            .collect::<io::Result<Vec<_>>>()?;
+        let names = read_fields(&mut reader, count, 33, Encoding::Latin1)?;
+        read_bytes(&mut reader, 2 * (count + 1))?;
+        let formats = read_fields(&mut reader, count, 49, Encoding::Latin1)?;
+        // Value label names and variable labels
+        read_bytes(&mut reader, (33 + 81) * count)?;
+        loop {
+            let expansion = read_bytes(&mut reader, 5)?;
+            let len = byte_order.u32(&expansion[1..]);
+            if expansion[0] == 0 && len == 0 {
+                break;
+            }
+            reader.seek(SeekFrom::Current(i64::from(len)))?;
+        }
+        Ok(StataReader::from_descriptors(
+            reader,
+            batch_size,
+            release,
+            byte_order,
+            observations,
+            types,
+            names,
+            formats,
+            HashMap::new(),
+        ))
+    }
+
+    /// Read the tagged header and descriptors of releases 117 to 119, leaving the reader at the
+    /// data
+    fn read_tagged(mut reader: R, batch_size: usize) -> Result<Self> {
+        expect(&mut reader, "<stata_dta><header><release>")?;
+        let release = String::from_utf8_lossy(&read_bytes(&mut reader, 3)?).into_owned();
+        let release = match release.parse::<u8>() {
+            Ok(release) if (117..=119).contains(&release) => release,
+            _ => return Err(invalid(format!("Unsupported Stata release {}", release)).into()),
+        };
+        expect(&mut reader, "</release><byteorder>")?;
+        let byte_order = match &read_bytes(&mut reader, 3)?[..] {
+            b"MSF" => ByteOrder::Big,
+            b"LSF" => ByteOrder::Little,
+            x => {
+                return Err(
+                    invalid(format!("Unknown byte order {}", String::from_utf8_lossy(x))).into(),
+                )
+            }
+        };
+        expect(&mut reader, "</byteorder><K>")?;
+        let count = if release >= 119 {
+            byte_order.u32(&read_bytes(&mut reader, 4)?) as usize
+        } else {
+            byte_order.u16(&read_bytes(&mut reader, 2)?) as usize
+        };
+        expect(&mut reader, "</K><N>")?;
+        let observations = if release >= 118 {
+            byte_order.u64(&read_bytes(&mut reader, 8)?)
+        } else {
+            u64::from(byte_order.u32(&read_bytes(&mut reader, 4)?))
+        };
+        expect(&mut reader, "</N><label>")?;
+        let label_len = if release >= 118 {
+            byte_order.u16(&read_bytes(&mut reader, 2)?) as usize
+        } else {
+            read_bytes(&mut reader, 1)?[0] as usize
+        };
+        read_bytes(&mut reader, label_len)?;
+        expect(&mut reader, "</label><timestamp>")?;
+        let timestamp_len = read_bytes(&mut reader, 1)?[0] as usize;
+        read_bytes(&mut reader, timestamp_len)?;
+        expect(&mut reader, "</timestamp></header><map>")?;
+        let map = read_bytes(&mut reader, 14 * 8)?
+            .chunks(8)
+            .map(|x| byte_order.u64(x))
+            .collect::<Vec<u64>>();
+
+        let (name_width, format_width) = if release >= 118 { (129, 57) } else { (33, 49) };
+        let encoding = encoding(release);
+        seek_tag(&mut reader, map[2], "<variable_types>")?;
+        let types = read_bytes(&mut reader, 2 * count)?
+            .chunks(2)
+            .map(|x| VariableType::from_code(byte_order.u16(x)))
+            .collect::<io::Result<Vec<_>>>()?;
+        seek_tag(&mut reader, map[3], "<varnames>")?;
+        let names = read_fields(&mut reader, count, name_width, encoding)?;
+        seek_tag(&mut reader, map[5], "<formats>")?;
+        let formats = read_fields(&mut reader, count, format_width, encoding)?;
+        seek_tag(&mut reader, map[10], "<strls>")?;
+        let strls = read_strls(&mut reader, release, byte_order)?;
+        seek_tag(&mut reader, map[9], "<data>")?;
+        Ok(StataReader::from_descriptors(
+            reader,
+            batch_size,
+            release,
+            byte_order,
+            observations,
+            types,
+            names,
+            formats,
+            strls,
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_descriptors(
+        reader: R,
+        batch_size: usize,
+        release: u8,
+        byte_order: ByteOrder,
+        observations: u64,
+        types: Vec<VariableType>,
+        names: Vec<String>,
+        formats: Vec<String>,
+        strls: HashMap<(u64, u64), String>,
+    ) -> Self {
+        let variables = types
+            .into_iter()
+            .zip(formats)
+            .map(|(variable_type, format)| Variable {
+                variable_type,
+                date_format: match variable_type {
+                    VariableType::Str(_) | VariableType::StrL => None,
+                    _ => DateFormat::from_format(&format),
+                },
+                offset: 0,
+            })
+            .collect::<Vec<Variable>>();
+        let fields = variables
+            .iter()
+            .zip(names)
+            .map(|(variable, name)| {
+                let data_type = match (variable.date_format, variable.variable_type) {
+                    (Some(date_format), _) => date_format.data_type(),
+                    (None, VariableType::Str(_)) | (None, VariableType::StrL) => DataType::Utf8,
+                    (None, VariableType::Byte) => DataType::Int8,
+                    (None, VariableType::Int) => DataType::Int16,
+                    (None, VariableType::Long) => DataType::Int32,
+                    (None, VariableType::Float) => DataType::Float32,
+                    (None, VariableType::Double) => DataType::Float64,
+                };
+                Field::new(&name, data_type, true)
+            })
+            .collect::<Vec<Field>>();
+        StataReader {
+            reader,
+            schema: Arc::new(Schema::new(fields)),
+            variables,
+            byte_order,
+            encoding: encoding(release),
+            release,
+            record_len: 0,
+            remaining: observations,
+            batch_size,
+            strls,
+        }
+    }
+
+    fn read_batch(&mut self, rows: usize) -> io::Result<RecordBatch> {
+        let records = read_bytes(&mut self.reader, rows * self.record_len)?;
+        let columns = self
+            .variables
+            .iter()
+            .map(|variable| self.column(variable, &records))
+            .collect::<io::Result<Vec<ArrayRef>>>()?;
+        RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn column(&self, variable: &Variable, records: &[u8]) -> io::Result<ArrayRef> {
+        let width = variable.variable_type.width();
+        let cells = records
+            .chunks(self.record_len)
+            .map(|record| &record[variable.offset..variable.offset + width]);
+        let byte_order = self.byte_order;
+        let numbers = cells
+            .clone()
+            .map(|cell| variable.variable_type.number(byte_order, cell));
+        Ok(match (variable.date_format, variable.variable_type) {
+            (Some(DateFormat::Date), _) => Arc::new(Date32Array::from(
+                numbers
+                    .map(|x| x.map(|x| unix_days(x, EPOCH_DAYS)).transpose())
+                    .collect::<io::Result<Vec<_>>>()?,
+            )),
+            (Some(DateFormat::DateTime), _) => Arc::new(TimestampMillisecondArray::from_opt_vec(
+                numbers
+                    .map(|x| {
+                        x.map(|x| unix_milliseconds(x, EPOCH_MILLISECONDS))
+                            .transpose()
+                    })
+                    .collect::<io::Result<Vec<_>>>()?,
+                None,
+            )),
+            (Some(DateFormat::LeapDateTime), _) => {
+                Arc::new(TimestampMillisecondArray::from_opt_vec(
+                    numbers
+                        .map(|x| {
+                            x.map(|x| {
+                                unix_milliseconds(x, EPOCH_MILLISECONDS).map(without_leap_seconds)
+                            })
+                            .transpose()
+                        })
+                        .collect::<io::Result<Vec<_>>>()?,
+                    None,
+                ))
+            }
+            (None, VariableType::Byte) => Arc::new(Int8Array::from(
+                numbers.map(|x| x.map(|x| x as i8)).collect::<Vec<_>>(),
+            )),
+            (None, VariableType::Int) => Arc::new(Int16Array::from(
+                numbers.map(|x| x.map(|x| x as i16)).collect::<Vec<_>>(),
+            )),
+            (None, VariableType::Long) => Arc::new(Int32Array::from(
+                numbers.map(|x| x.map(|x| x as i32)).collect::<Vec<_>>(),
+            )),
+            (None, VariableType::Float) => Arc::new(Float32Array::from(
+                numbers.map(|x| x.map(|x| x as f32)).collect::<Vec<_>>(),
+            )),
+            (None, VariableType::Double) => {
+                Arc::new(Float64Array::from(numbers.collect::<Vec<_>>()))
+            }
+            (None, VariableType::Str(_)) => Arc::new(StringArray::from(
+                cells
+                    .map(|cell| self.encoding.decode(until_null(cell)))
+                    .collect::<io::Result<Vec<String>>>()?,
+            )),
+            (None, VariableType::StrL) => Arc::new(StringArray::from(
+                cells
+                    .map(|cell| self.strl(cell))
+                    .collect::<io::Result<Vec<String>>>()?,
+            )),
+        })
+    }
+
+    /// Look up the long string that a strL cell refers to
+    fn strl(&self, cell: &[u8]) -> io::Result<String> {
+        let split = match self.release {
+            117 => 4,
+            118 => 2,
+            _ => 3,
+        };
+        let key = (
+            self.byte_order.uint(&cell[..split]),
+            self.byte_order.uint(&cell[split..]),
+        );
+        if key == (0, 0) {
+            return Ok(String::new());
+        }
+        self.strls.get(&key).cloned().ok_or_else(|| {
+            invalid(format!(
+                "Missing strL of variable {} and observation {}",
+                key.0, key.1
+            ))
+        })
+    }
+}
+
+impl<R: Read + Seek> Iterator for StataReader<R> {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let rows = self.remaining.min(self.batch_size as u64) as usize;
+        self.remaining -= rows as u64;
+        let batch = self.read_batch(rows);
+        if batch.is_err() {
+            self.remaining = 0;
+        }
+        Some(batch.map_err(|e| e.into()))
+    }
+}
+
+impl<R: Read + Seek> RecordBatchReader for StataReader<R> {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Convert Unix milliseconds that count leap seconds into Unix milliseconds that do not
+///
+/// A time within a leap second is read as the second before it.
+fn without_leap_seconds(milliseconds: i64) -> i64 {
+    let leap_seconds = LEAP_SECONDS
+        .iter()
+        .enumerate()
+        .take_while(|(count, x)| milliseconds >= (**x + *count as i64) * 1_000)
+        .count() as i64;
+    milliseconds - leap_seconds * 1_000
+}
+
+/// Read the long strings of releases 117 and later, which follow the `<strls>` tag
+fn read_strls<R: Read>(
+    reader: &mut R,
+    release: u8,
+    byte_order: ByteOrder,
+) -> io::Result<HashMap<(u64, u64), String>> {
+    let mut strls = HashMap::new();
+    loop {
+        match &read_bytes(reader, 3)?[..] {
+            b"GSO" => {}
+            b"</s" => {
+                expect(reader, "trls>")?;
+                return Ok(strls);
+            }
+            _ => return Err(invalid("Invalid strL".to_string())),
+        }
+        let variable = u64::from(byte_order.u32(&read_bytes(reader, 4)?));
+        let observation = if release >= 118 {
+            byte_order.u64(&read_bytes(reader, 8)?)
+        } else {
+            u64::from(byte_order.u32(&read_bytes(reader, 4)?))
+        };
+        let strl_type = read_bytes(reader, 1)?[0];
+        let len = byte_order.u32(&read_bytes(reader, 4)?) as usize;
+        let data = read_bytes(reader, len)?;
+        // Text strLs (130) include their terminating null, while binary strLs (129) are read as
+        // text when they decode
+        let data = match strl_type {
+            130 => until_null(&data),
+            _ => &data[..],
+        };
+        strls.insert((variable, observation), encoding(release).decode(data)?);
+    }
+}
+
+fn expect<R: Read>(reader: &mut R, tag: &str) -> io::Result<()> {
+    if read_bytes(reader, tag.len())? != tag.as_bytes() {
+        return Err(invalid(format!("Expected {}", tag)));
+    }
+    Ok(())
+}
+
+fn seek_tag<R: Read + Seek>(reader: &mut R, offset: u64, tag: &str) -> io::Result<()> {
+    reader.seek(SeekFrom::Start(offset))?;
+    expect(reader, tag)
+}
+
+/// Read `count` null-terminated strings stored in fields of `width` bytes
+fn read_fields<R: Read>(
+    reader: &mut R,
+    count: usize,
+    width: usize,
+    encoding: Encoding,
+) -> io::Result<Vec<String>> {
+    read_bytes(reader, count * width)?
+        .chunks(width)
+        .map(|x| encoding.decode(until_null(x)))
+        .collect()
+}
+
+/// Strings of releases before 118 are Latin-1 rather than UTF-8
+fn encoding(release: u8) -> Encoding {
+    if release >= 118 {
+        Encoding::Utf8
+    } else {
+        Encoding::Latin1
+    }
+}
+
+fn until_null(bytes: &[u8]) -> &[u8] {
+    match bytes.iter().position(|x| *x == 0) {
+        Some(end) => &bytes[..end],
+        None => bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use arrow::array::Array;
+
+    use super::*;
+
+    fn field(value: &str, width: usize) -> Vec<u8> {
+        let mut field = value.as_bytes().to_vec();
+        field.resize(width, 0);
+        field
+    }
+
+    /// Write a big-endian file of release 114
+    fn old_dta(variables: &[(&str, u8, &str)], records: &[Vec<u8>]) -> Vec<u8> {
+        let mut dta = vec![114, 1, 1, 0];
+        dta.extend((variables.len() as u16).to_be_bytes());
+        dta.extend((records.len() as u32).to_be_bytes());
+        dta.extend([0; 81 + 18]);
+        dta.extend(variables.iter().map(|x| x.1));
+        for (name, _, _) in variables {
+            dta.extend(field(name, 33));
+        }
+        dta.extend(vec![0; 2 * (variables.len() + 1)]);
+        for (_, _, format) in variables {
+            dta.extend(field(format, 49));
+        }
+        dta.extend(vec![0; (33 + 81) * variables.len()]);
+        dta.push(1);
+        dta.extend(3u32.to_be_bytes());
+        dta.extend(b"abc");
+        dta.extend([0; 5]);
+        dta.extend(records.concat());
+        dta
+    }
+
+    /// Write a little-endian file of release 117 or later
+    fn tagged_dta(
+        release: u8,
+        variables: &[(&str, u16, &str)],
+        records: &[Vec<u8>],
+        strls: &[(u32, u64, &str)],
+    ) -> Vec<u8> {
+        let (name_width, format_width) = if release >= 118 { (129, 57) } else { (33, 49) };
+        let count = variables.len();
+        let mut dta = format!(
+            "<stata_dta><header><release>{}</release><byteorder>LSF</byteorder><K>",
+            release
+        )
+        .into_bytes();
+        if release >= 119 {
+            dta.extend((count as u32).to_le_bytes());
+        } else {
+            dta.extend((count as u16).to_le_bytes());
+        }
+        dta.extend(b"</K><N>");
+        if release >= 118 {
+            dta.extend((records.len() as u64).to_le_bytes());
+        } else {
+            dta.extend((records.len() as u32).to_le_bytes());
+        }
+        dta.extend(b"</N><label>");
+        if release >= 118 {
+            dta.extend(0u16.to_le_bytes());
+        } else {
+            dta.push(0);
+        }
+        dta.extend(b"</label><timestamp>\0</timestamp></header><map>");
+        let map_start = dta.len();
+        dta.extend([0; 14 * 8]);
+        dta.extend(b"</map>");
+        let mut map = [0; 14];
+        map[2] = dta.len();
+        dta.extend(b"<variable_types>");
+        for (_, code, _) in variables {
+            dta.extend(code.to_le_bytes());
+        }
+        dta.extend(b"</variable_types>");
+        map[3] = dta.len();
+        dta.extend(b"<varnames>");
+        for (name, _, _) in variables {
+            dta.extend(field(name, name_width));
+        }
+        dta.extend(b"</varnames>");
+        map[4] = dta.len();
+        dta.extend(b"<sortlist>");
+        dta.extend(vec![0; (count + 1) * if release >= 119 { 4 } else { 2 }]);
+        dta.extend(b"</sortlist>");
+        map[5] = dta.len();
+        dta.extend(b"<formats>");
+        for (_, _, format) in variables {
+            dta.extend(field(format, format_width));
+        }
+        dta.extend(b"</formats>");
+        map[9] = dta.len();
+        dta.extend(b"<data>");
+        dta.extend(records.concat());
+        dta.extend(b"</data>");
+        map[10] = dta.len();
+        dta.extend(b"<strls>");
+        for (variable, observation, value) in strls {
+            dta.extend(b"GSO");
+            dta.extend(variable.to_le_bytes());
+            if release >= 118 {
+                dta.extend(observation.to_le_bytes());
+            } else {
+                dta.extend((*observation as u32).to_le_bytes());
+            }
+            dta.push(130);
+            dta.extend((value.len() as u32 + 1).to_le_bytes());
+            dta.extend(value.as_bytes());
+            dta.push(0);
+        }
+        dta.extend(b"</strls>");
+        for (index, offset) in map.iter().enumerate() {
+            dta[map_start + 8 * index..map_start + 8 * index + 8]
+                .copy_from_slice(&(*offset as u64).to_le_bytes());
+        }
+        dta
+    }
+
+    fn read(dta: Vec<u8>, batch_size: usize) -> Vec<RecordBatch> {
+        StataReader::try_new(Cursor::new(dta), batch_size)
+            .unwrap()
+            .collect::<ArrowResult<Vec<RecordBatch>>>()
+            .unwrap()
+    }
+
+    fn column<T: 'static>(batch: &RecordBatch, index: usize) -> &T {
+        batch.column(index).as_any().downcast_ref::<T>().unwrap()
+    }
+
+    #[test]
+    fn numeric_types_and_missing_values() {
+        let record = |b: i8, i: i16, l: i32, f: f32, d: f64, s: &str| {
+            let mut record = vec![b as u8];
+            record.extend(i.to_be_bytes());
+            record.extend(l.to_be_bytes());
+            record.extend(f.to_be_bytes());
+            record.extend(d.to_be_bytes());
+            record.extend(field(s, 5));
+            record
+        };
+        let records = vec![
+            record(1, -2, 3, 1.5, 2.5, "ab"),
+            // `.`
+            record(
+                101,
+                32_741,
+                2_147_483_621,
+                f32::from_bits(0x7f00_0000),
+                f64::from_bits(0x7fe0_0000_0000_0000),
+                "",
+            ),
+            // `.z`
+            record(
+                127,
+                32_767,
+                2_147_483_647,
+                f32::from_bits(0x7f00_d000),
+                f64::from_bits(0x7fe0_1a00_0000_0000),
+                "",
+            ),
+            record(
+                100,
+                32_740,
+                2_147_483_620,
+                f32::from_bits(MAX_FLOAT_BITS),
+                f64::from_bits(MAX_DOUBLE_BITS),
+                "hello",
+            ),
+            record(-127, -32_767, -2_147_483_647, -1.0, -1.0, "x"),
+        ];
+        let variables = [
+            ("b", 251, "%8.0g"),
+            ("i", 252, "%8.0g"),
+            ("l", 253, "%12.0g"),
+            ("f", 254, "%9.0g"),
+            ("d", 255, "%10.0g"),
+            ("s", 5, "%9s"),
+        ];
+        let reader = StataReader::try_new(Cursor::new(old_dta(&variables, &records)), 2).unwrap();
+        let data_types = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|x| x.data_type().clone())
+            .collect::<Vec<DataType>>();
+        assert_eq!(
+            data_types,
+            vec![
+                DataType::Int8,
+                DataType::Int16,
+                DataType::Int32,
+                DataType::Float32,
+                DataType::Float64,
+                DataType::Utf8
+            ]
+        );
+        let batches = reader.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        assert_eq!(
+            batches.iter().map(|x| x.num_rows()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        for index in 0..5 {
+            assert!(batches[0].column(index).is_valid(0));
+            assert!(batches[0].column(index).is_null(1));
+            assert!(batches[1].column(index).is_null(0));
+            assert!(batches[1].column(index).is_valid(1));
+        }
+        assert_eq!(column::<Int8Array>(&batches[1], 0).value(1), 100);
+        assert_eq!(column::<Int32Array>(&batches[1], 2).value(1), 2_147_483_620);
+        assert_eq!(column::<Int16Array>(&batches[2], 1).value(0), -32_767);
+        assert_eq!(column::<Float64Array>(&batches[0], 4).value(0), 2.5);
+        let strings = column::<StringArray>(&batches[0], 5);
+        assert_eq!(strings.value(0), "ab");
+        assert_eq!(strings.value(1), "");
+        assert_eq!(column::<StringArray>(&batches[1], 5).value(1), "hello");
+    }
+
+    #[test]
+    fn dates() {
+        let record = |date: i32, datetime: f64, leap: f64, month: i16| {
+            let mut record = date.to_le_bytes().to_vec();
+            record.extend(datetime.to_le_bytes());
+            record.extend(leap.to_le_bytes());
+            record.extend(month.to_le_bytes());
+            record
+        };
+        let stata_ms = |unix_ms: i64| (unix_ms + EPOCH_MILLISECONDS) as f64;
+        let records = vec![
+            record(0, stata_ms(0), stata_ms(63_072_000_000), 720),
+            // 2020-01-01, 2017-01-01T00:00:00 and 2016-12-31T23:59:60
+            record(
+                21_915,
+                stata_ms(1_483_228_800_000),
+                stata_ms(1_483_228_800_000 + 26_000),
+                -1,
+            ),
+            record(
+                2_147_483_621,
+                f64::from_bits(0x7fe0_0000_0000_0000),
+                stata_ms(1_483_228_800_000 + 27_000),
+                32_741,
+            ),
+        ];
+        let variables = [
+            ("date", 65528, "%tdCCYY-NN-DD"),
+            ("datetime", 65526, "%tc"),
+            ("leap", 65526, "%-tC"),
+            ("month", 65529, "%tm"),
+        ];
+        let reader =
+            StataReader::try_new(Cursor::new(tagged_dta(118, &variables, &records, &[])), 10)
+                .unwrap();
+        let schema = reader.schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Date32);
+        assert_eq!(
+            schema.field(2).data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, None)
+        );
+        assert_eq!(schema.field(3).data_type(), &DataType::Int16);
+        let batches = reader.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        let date = column::<Date32Array>(&batches[0], 0);
+        assert_eq!(date.value(0), -3_653);
+        assert_eq!(date.value(1), 18_262);
+        assert!(date.is_null(2));
+        let datetime = column::<TimestampMillisecondArray>(&batches[0], 1);
+        assert_eq!(datetime.value(0), 0);
+        assert_eq!(datetime.value(1), 1_483_228_800_000);
+        assert!(datetime.is_null(2));
+        let leap = column::<TimestampMillisecondArray>(&batches[0], 2);
+        assert_eq!(leap.value(0), 63_072_000_000);
+        assert_eq!(leap.value(1), 1_483_228_799_000);
+        assert_eq!(leap.value(2), 1_483_228_800_000);
+        let month = column::<Int16Array>(&batches[0], 3);
+        assert_eq!(month.value(0), 720);
+        assert!(month.is_null(2));
+    }
+
+    #[test]
+    fn extreme_dates() {
+        // The largest long that is not missing, as a %td, and a huge double, as a %tc and %tC,
+        // are both far beyond the years that dates can be normalized for
+        let cases: Vec<(u16, &str, Vec<u8>)> = vec![
+            (65528, "%td", 2_147_483_620i32.to_le_bytes().to_vec()),
+            (65526, "%td", (-1e300f64).to_le_bytes().to_vec()),
+            (65526, "%tc", 1e300f64.to_le_bytes().to_vec()),
+            (65526, "%tC", 9.3e18f64.to_le_bytes().to_vec()),
+        ];
+        for (variable_type, format, record) in cases {
+            let dta = tagged_dta(118, &[("date", variable_type, format)], &[record], &[]);
+            let batches = StataReader::try_new(Cursor::new(dta), 10)
+                .unwrap()
+                .collect::<ArrowResult<Vec<RecordBatch>>>();
+            assert!(batches.is_err(), "{}", format);
+        }
+    }
+
+    #[test]
+    fn old_date_format() {
+        let records = vec![1i32.to_be_bytes().to_vec()];
+        let batches = read(old_dta(&[("date", 253, "%dD_m_Y")], &records), 10);
+        assert_eq!(column::<Date32Array>(&batches[0], 0).value(0), -3_652);
+    }
+
+    #[test]
+    fn strls() {
+        for release in 117..=119 {
+            let split = match release {
+                117 => 32,
+                118 => 16,
+                _ => 24,
+            };
+            let record = |id: &str, variable: u64, observation: u64| {
+                let mut record = field(id, 3);
+                record.extend(((observation << split) | variable).to_le_bytes());
+                record
+            };
+            let records = vec![record("a", 2, 1), record("b", 0, 0), record("c", 2, 1)];
+            let dta = tagged_dta(
+                release,
+                &[("id", 3, "%9s"), ("notes", 32768, "%9s")],
+                &records,
+                &[(2, 1, "A long string")],
+            );
+            let batches = read(dta, 10);
+            let notes = column::<StringArray>(&batches[0], 1);
+            assert_eq!(notes.value(0), "A long string");
+            assert_eq!(notes.value(1), "");
+            assert_eq!(notes.value(2), "A long string");
+            assert_eq!(column::<StringArray>(&batches[0], 0).value(2), "c");
+        }
+    }
+
+    #[test]
+    fn unsupported_release() {
+        let mut old = old_dta(&[("x", 251, "%8.0g")], &[vec![1]]);
+        old[0] = 113;
+        assert!(StataReader::try_new(Cursor::new(old), 10).is_err());
+        let tagged = tagged_dta(120, &[("x", 65530, "%8.0g")], &[vec![1]], &[]);
+        assert!(StataReader::try_new(Cursor::new(tagged), 10).is_err());
+    }
+}
@@ -1,156 +1,449 @@
-use arrow::array::{
-    Array, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, StringArray,
-    UInt16Array, UInt32Array, UInt64Array,
+use crate::{
+    config::{UnfConfig, UnfVersion},
+    error::{Result, UnfError},
+    numeric::{float_form, integer_form, scaled_form},
 };
-use std::{
-    convert::TryFrom,
-    fmt::{self},
+use arrow::{
+    array::{
+        Array, ArrayData, BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray,
+        FixedSizeBinaryArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array,
+        Int8Array, LargeBinaryArray, LargeStringArray, NullArray, PrimitiveArray, StringArray,
+        Time32MillisecondArray, Time32SecondArray, Time64MicrosecondArray, Time64NanosecondArray,
+        TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
+        TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+    },
+    datatypes::{ArrowPrimitiveType, DataType},
 };
+use chrono::{NaiveDateTime, Timelike};
+use half::f16;
+
+/// Encoding of a missing value, which is not terminated like other values
+pub(crate) const MISSING_VALUE: [u8; 3] = [0, 0, 0];
 
 pub trait UNFVector {
-    fn raw(&self, characters: usize, digits: u32) -> Vec<Vec<u8>> {
-        self.to_unf(digits)
+    fn raw(&self, config: &UnfConfig) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .to_unf(config)?
             .iter()
-            .map(|x| {
-                let mut characters = x.chars().take(characters).collect::<Vec<char>>();
-                characters.push('\n');
-                characters.push('\x00');
-                characters
-                    .into_iter()
-                    .collect::<String>()
-                    .as_bytes()
-                    .to_vec()
+            .map(|x| match x {
+                Some(x) => terminated(x.chars().take(config.characters).collect(), config.version),
+                None => MISSING_VALUE.to_vec(),
             })
-            .collect()
+            .collect())
     }
-    fn to_unf(&self, digits: u32) -> Vec<String>;
+    /// Normalize each value, with missing values represented as `None`
+    ///
+    /// Values without a normalization, such as dates beyond the range of the calendar,
+    /// are errors rather than being fingerprinted as something else.
+    fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>>;
 }
 
-fn exp_form<T>(value: T) -> String
-where
-    T: fmt::Debug + fmt::LowerExp,
-{
-    let string_rep = format!("{:+e}", value);
-    let (pre, post) = string_rep.split_once('e').unwrap();
-    let mut formatted_post = post.to_string();
-    if post.starts_with('0') {
-        formatted_post = vec!['+'].into_iter().chain(post.chars().skip(1)).collect();
-    }
-    if pre.len() == 2 {
-        format!("{}.e{}", pre, formatted_post)
-    } else {
-        format!("{}e{}", pre, formatted_post)
+impl<T: UNFVector + ?Sized> UNFVector for &T {
+    fn raw(&self, config: &UnfConfig) -> Result<Vec<Vec<u8>>> {
+        (**self).raw(config)
+    }
+
+    fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        (**self).to_unf(config)
     }
 }
 
-fn sigfig<T>(x: T, digits: u32) -> T
-where
-    T: num_traits::float::Float + TryFrom<u32> + TryFrom<f32>,
-    <T as TryFrom<f32>>::Error: std::fmt::Debug,
-    <T as TryFrom<u32>>::Error: std::fmt::Debug,
-{
-    let reduction: T = x.log10();
-    let digit_modifier = if reduction < T::zero() {
-        reduction.abs().ceil()
-    } else {
-        T::zero()
-    };
+/// Terminate a normalized value and encode it into the bytes that are hashed
+pub(crate) fn terminated(mut characters: Vec<char>, version: UnfVersion) -> Vec<u8> {
+    characters.push('\n');
+    characters.push('\x00');
+    encode_characters(characters, version)
+}
 
-    let digits: T = TryFrom::try_from(digits).unwrap();
-    let base_modifier: T = TryFrom::try_from(10.0_f32).unwrap();
-    let rounding_factor = base_modifier.powf(digits + digit_modifier);
-    (x * rounding_factor).round() / rounding_factor
-}
-
-impl UNFVector for Float64Array {
-    fn to_unf(&self, digits: u32) -> Vec<String> {
-        if self.null_count() == 0 {
-            self.values()
-                .iter()
-                .map(|x| exp_form(sigfig(*x, digits - 1)))
-                .collect()
-        } else {
-            let mut out: Vec<String> = Vec::with_capacity(self.len());
-            for index in 0..self.len() {
-                if self.is_null(index) {
-                    out.push("+nan".to_string())
+/// Encode normalized characters into the bytes that are hashed
+///
+/// UNF V3 and V4 encode characters as UTF-32BE, which was changed to UTF-8 from V4.1 onwards.
+fn encode_characters(characters: Vec<char>, version: UnfVersion) -> Vec<u8> {
+    match version {
+        UnfVersion::Three | UnfVersion::Four => characters
+            .into_iter()
+            .flat_map(|x| u32::from(x).to_be_bytes())
+            .collect(),
+        _ => characters
+            .into_iter()
+            .collect::<String>()
+            .as_bytes()
+            .to_vec(),
+    }
+}
+
+/// Normalize floating point values of any width
+///
+/// Values are widened to `f64`, which is exact for every IEEE-754 binary16 and
+/// binary32 value, so a column fingerprints identically whichever float width
+/// a reader chose for it.
+macro_rules! float_unf {
+    ($array_type: ident) => {
+        impl UNFVector for $array_type {
+            fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>> {
+                if self.null_count() == 0 {
+                    Ok(self
+                        .values()
+                        .iter()
+                        .map(|x| Some(float_form(f64::from(*x), config.digits, config.rounding)))
+                        .collect())
                 } else {
-                    out.push(exp_form(sigfig(self.value(index), digits - 1)))
+                    let mut out: Vec<Option<String>> = Vec::with_capacity(self.len());
+                    for index in 0..self.len() {
+                        if self.is_null(index) {
+                            out.push(None)
+                        } else {
+                            out.push(Some(float_form(
+                                f64::from(self.value(index)),
+                                config.digits,
+                                config.rounding,
+                            )));
+                        }
+                    }
+                    Ok(out)
                 }
             }
-            out
         }
-    }
+    };
 }
 
-impl UNFVector for Float32Array {
-    fn to_unf(&self, digits: u32) -> Vec<String> {
-        let scaling_factor = 10_u32.pow(digits) as f32;
-        if self.null_count() == 0 {
-            self.values()
-                .iter()
-                .map(|x| exp_form((x * scaling_factor).round() / scaling_factor))
-                .collect()
-        } else {
-            let mut out: Vec<String> = Vec::with_capacity(self.len());
-            for index in 0..self.len() {
-                if self.is_null(index) {
-                    out.push("+nan".to_string())
+float_unf!(Float32Array);
+float_unf!(Float64Array);
+
+/// Half precision floats, which Arrow stores as raw 16 bit values
+pub(crate) struct Float16Values<'a>(pub(crate) &'a ArrayData);
+
+impl<'a> UNFVector for Float16Values<'a> {
+    fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        let data = self.0;
+        let values = data.buffers()[0].as_slice();
+        Ok((0..data.len())
+            .map(|index| {
+                if data.is_null(index) {
+                    None
                 } else {
-                    out.push(exp_form(
-                        (self.value(index) * scaling_factor).round() / scaling_factor,
-                    ));
+                    let position = (data.offset() + index) * 2;
+                    let bits = u16::from_le_bytes([values[position], values[position + 1]]);
+                    Some(float_form(
+                        f64::from(f16::from_bits(bits)),
+                        config.digits,
+                        config.rounding,
+                    ))
                 }
-            }
-            out
-        }
+            })
+            .collect())
     }
 }
 
 macro_rules! integer_unf {
     ($array_type: ident) => {
         impl UNFVector for $array_type {
-            fn to_unf(&self, _digits: u32) -> Vec<String> {
+            fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>> {
                 if self.null_count() == 0 {
-                    self.values().iter().map(exp_form).collect()
+                    Ok(self
+                        .values()
+                        .iter()
+                        .map(|x| Some(integer_form(x, config.digits, config.rounding)))
+                        .collect())
                 } else {
-                    let mut out: Vec<String> = Vec::with_capacity(self.len());
+                    let mut out: Vec<Option<String>> = Vec::with_capacity(self.len());
                     for index in 0..self.len() {
                         if self.is_null(index) {
-                            out.push("+nan".to_string())
+                            out.push(None)
                         } else {
-                            out.push(exp_form(self.value(index)));
+                            out.push(Some(integer_form(
+                                self.value(index),
+                                config.digits,
+                                config.rounding,
+                            )));
                         }
                     }
-                    out
+                    Ok(out)
                 }
             }
         }
     };
 }
 
+integer_unf!(Int8Array);
 integer_unf!(Int16Array);
 integer_unf!(Int32Array);
 integer_unf!(Int64Array);
 
+integer_unf!(UInt8Array);
 integer_unf!(UInt16Array);
 integer_unf!(UInt32Array);
 integer_unf!(UInt64Array);
 
-impl UNFVector for StringArray {
-    fn to_unf(&self, _digits: u32) -> Vec<String> {
-        let mut out: Vec<String> = Vec::with_capacity(self.len());
-        for index in 0..self.len() {
-            if self.is_null(index) {
-                out.push("".to_string())
+/// Decimals are normalized directly from their scaled integers, without passing through
+/// a float, so rounding is exact for every precision.
+impl UNFVector for DecimalArray {
+    fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        let scale = self.scale() as i64;
+        Ok((0..self.len())
+            .map(|index| {
+                if self.is_null(index) {
+                    None
+                } else {
+                    let value = self.value(index);
+                    Some(scaled_form(
+                        value < 0,
+                        &value.unsigned_abs().to_string(),
+                        scale,
+                        config.digits,
+                        config.rounding,
+                    ))
+                }
+            })
+            .collect())
+    }
+}
+
+/// Every value of a `Null` column is missing, although the array itself has no null bitmap
+impl UNFVector for NullArray {
+    fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        Ok(vec![None; self.len()])
+    }
+}
+
+/// Booleans are normalized as the numbers 0 and 1
+impl UNFVector for BooleanArray {
+    fn to_unf(&self, config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        Ok((0..self.len())
+            .map(|index| {
+                if self.is_null(index) {
+                    None
+                } else {
+                    Some(integer_form(
+                        u8::from(self.value(index)),
+                        config.digits,
+                        config.rounding,
+                    ))
+                }
+            })
+            .collect())
+    }
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Normalize a time of day as `hh:mm:ss`, with any fractional seconds stripped of trailing zeros
+fn time_form(seconds: u32, nanoseconds: u32) -> String {
+    let hms = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    );
+    if nanoseconds == 0 {
+        hms
+    } else {
+        let fraction = format!("{:09}", nanoseconds);
+        format!("{}.{}", hms, fraction.trim_end_matches('0'))
+    }
+}
+
+/// Normalize a count of `units_per_second` since midnight
+///
+/// Counts that are negative, or that reach the next midnight, are out of range.
+fn time_of_day_form(value: i64, units_per_second: i64) -> Result<String> {
+    if !(0..SECONDS_PER_DAY * units_per_second).contains(&value) {
+        return Err(UnfError::OutOfRange(format!(
+            "Time of {} units of 1/{} second is not within a day",
+            value, units_per_second
+        )));
+    }
+    Ok(time_form(
+        (value / units_per_second) as u32,
+        ((value % units_per_second) * (1_000_000_000 / units_per_second)) as u32,
+    ))
+}
+
+/// Normalize a count of `units_per_second` since the epoch as `YYYY-MM-DDThh:mm:ss`
+///
+/// Date-times known to be in UTC carry a `Z` suffix. Instants beyond the years that the
+/// calendar represents are out of range.
+fn datetime_form(value: i64, units_per_second: i64, utc: bool) -> Result<String> {
+    let seconds = value.div_euclid(units_per_second);
+    let nanoseconds = value.rem_euclid(units_per_second) * (1_000_000_000 / units_per_second);
+    let datetime =
+        NaiveDateTime::from_timestamp_opt(seconds, nanoseconds as u32).ok_or_else(|| {
+            UnfError::OutOfRange(format!(
+                "Timestamp of {} units of 1/{} second is beyond the supported dates",
+                value, units_per_second
+            ))
+        })?;
+    Ok(format!(
+        "{}T{}{}",
+        datetime.date().format("%Y-%m-%d"),
+        time_form(
+            datetime.time().num_seconds_from_midnight(),
+            datetime.time().nanosecond()
+        ),
+        if utc { "Z" } else { "" }
+    ))
+}
+
+fn date_form(days: i64) -> Result<String> {
+    days.checked_mul(SECONDS_PER_DAY)
+        .and_then(|seconds| NaiveDateTime::from_timestamp_opt(seconds, 0))
+        .map(|datetime| datetime.date().format("%Y-%m-%d").to_string())
+        .ok_or_else(|| {
+            UnfError::OutOfRange(format!(
+                "Date of {} days is beyond the supported dates",
+                days
+            ))
+        })
+}
+
+/// Apply a normalization to every value of a primitive array, leaving missing values as `None`
+fn temporal_unf<T, F>(array: &PrimitiveArray<T>, normalize: F) -> Result<Vec<Option<String>>>
+where
+    T: ArrowPrimitiveType,
+    F: Fn(T::Native) -> Result<String>,
+{
+    (0..array.len())
+        .map(|index| {
+            if array.is_null(index) {
+                Ok(None)
+            } else {
+                normalize(array.value(index)).map(Some)
+            }
+        })
+        .collect()
+}
+
+impl UNFVector for Date32Array {
+    fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        temporal_unf(self, |x| date_form(i64::from(x)))
+    }
+}
+
+/// Date64 values are normally whole days, but readers also use them for date-times
+///
+/// Values that fall part way through a day are normalized as date-times without a time zone.
+impl UNFVector for Date64Array {
+    fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+        let milliseconds_per_day = SECONDS_PER_DAY * 1000;
+        temporal_unf(self, |x| {
+            if x % milliseconds_per_day == 0 {
+                date_form(x / milliseconds_per_day)
             } else {
-                out.push(self.value(index).to_string());
+                datetime_form(x, 1000, false)
+            }
+        })
+    }
+}
+
+macro_rules! time_unf {
+    ($array_type: ident, $units_per_second: expr) => {
+        impl UNFVector for $array_type {
+            fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+                temporal_unf(self, |x| time_of_day_form(i64::from(x), $units_per_second))
+            }
+        }
+    };
+}
+
+time_unf!(Time32SecondArray, 1);
+time_unf!(Time32MillisecondArray, 1_000);
+time_unf!(Time64MicrosecondArray, 1_000_000);
+time_unf!(Time64NanosecondArray, 1_000_000_000);
+
+/// Arrow stores timestamps with a time zone as UTC instants, so those are normalized with a
+/// `Z` suffix while timestamps without a time zone are left as local date-times.
+macro_rules! timestamp_unf {
+    ($array_type: ident, $units_per_second: expr) => {
+        impl UNFVector for $array_type {
+            fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+                let utc = matches!(self.data_type(), DataType::Timestamp(_, Some(_)));
+                temporal_unf(self, |x| datetime_form(x, $units_per_second, utc))
             }
         }
-        out
+    };
+}
+
+timestamp_unf!(TimestampSecondArray, 1);
+timestamp_unf!(TimestampMillisecondArray, 1_000);
+timestamp_unf!(TimestampMicrosecondArray, 1_000_000);
+timestamp_unf!(TimestampNanosecondArray, 1_000_000_000);
+
+/// Normalize bytes as a big-endian bit field of `0` and `1`, without leading zero bits
+///
+/// A bit field with no set bits, including an empty one, is normalized as `0`.
+fn bit_form(value: &[u8]) -> String {
+    let bits = value
+        .iter()
+        .map(|x| format!("{:08b}", x))
+        .collect::<String>();
+    match bits.trim_start_matches('0') {
+        "" => "0".to_string(),
+        bits => bits.to_string(),
     }
 }
 
+/// Binary values are treated as bit fields, as the specification describes
+///
+/// Arbitrary byte blobs are normalized the same way, so leading zero bytes are not
+/// significant: in a variable width column, blobs that differ only by leading zero bytes
+/// fingerprint identically, while fixed width values such as UUIDs are unaffected.
+/// Bit fields are not character strings, so `characters` does not truncate them and
+/// every bit of a hash or UUID contributes to the fingerprint.
+macro_rules! binary_unf {
+    ($array_type: ident) => {
+        impl UNFVector for $array_type {
+            fn raw(&self, config: &UnfConfig) -> Result<Vec<Vec<u8>>> {
+                Ok(self
+                    .to_unf(config)?
+                    .iter()
+                    .map(|x| match x {
+                        Some(x) => terminated(x.chars().collect(), config.version),
+                        None => MISSING_VALUE.to_vec(),
+                    })
+                    .collect())
+            }
+
+            fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+                Ok((0..self.len())
+                    .map(|index| {
+                        if self.is_null(index) {
+                            None
+                        } else {
+                            Some(bit_form(self.value(index)))
+                        }
+                    })
+                    .collect())
+            }
+        }
+    };
+}
+
+binary_unf!(BinaryArray);
+binary_unf!(LargeBinaryArray);
+binary_unf!(FixedSizeBinaryArray);
+
+macro_rules! string_unf {
+    ($array_type: ident) => {
+        impl UNFVector for $array_type {
+            fn to_unf(&self, _config: &UnfConfig) -> Result<Vec<Option<String>>> {
+                let mut out: Vec<Option<String>> = Vec::with_capacity(self.len());
+                for index in 0..self.len() {
+                    if self.is_null(index) {
+                        out.push(None)
+                    } else {
+                        out.push(Some(self.value(index).to_string()));
+                    }
+                }
+                Ok(out)
+            }
+        }
+    };
+}
+
+string_unf!(StringArray);
+string_unf!(LargeStringArray);
+
+#[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
@@ -158,14 +451,55 @@ mod tests {
 
     use super::*;
     use arrow::{
+        array::{ArrayDataBuilder, ArrayRef, DecimalBuilder},
+        buffer::Buffer,
         datatypes::{DataType, Field, Schema},
         record_batch::RecordBatch,
     };
 
+    fn normalized<T: UNFVector>(array: T) -> Vec<Option<String>> {
+        array.to_unf(&UnfConfigBuilder::new().build()).unwrap()
+    }
+
+    /// Normalized values of an array without missing values
+    fn normalized_values<T: UNFVector>(array: T) -> Vec<String> {
+        normalized(array).into_iter().flatten().collect()
+    }
+
+    /// Bytes that are hashed for every value of an array
+    fn encoded<T: UNFVector>(array: T, config: &UnfConfig) -> Vec<u8> {
+        array.raw(config).unwrap().concat()
+    }
+
+    /// Short hash of each column of a batch
+    fn column_hashes(schema: Arc<Schema>, columns: Vec<ArrayRef>) -> Vec<String> {
+        let data_batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let config = UnfConfigBuilder::new().build();
+        let mut unf_hash = crate::hash_builder::UnfHashBuilder::new(schema, config);
+        unf_hash.hash(data_batch).unwrap();
+        unf_hash
+            .finalize()
+            .unwrap()
+            .into_iter()
+            .map(|x| x.short_hash)
+            .collect()
+    }
+
+    fn decimal_array(values: &[Option<i128>], precision: usize, scale: usize) -> DecimalArray {
+        let mut builder = DecimalBuilder::new(values.len(), precision, scale);
+        for value in values {
+            match value {
+                Some(value) => builder.append_value(*value).unwrap(),
+                None => builder.append_null().unwrap(),
+            }
+        }
+        builder.finish()
+    }
+
     #[test]
     fn float64_unf() {
         assert_eq!(
-            Float64Array::from(vec![
+            normalized_values(Float64Array::from(vec![
                 0.107352613238618,
                 0.0139041461516172,
                 0.573460578685626,
@@ -176,8 +510,7 @@ mod tests {
                 0.724982571555302,
                 0.977011684328318,
                 0.358048414811492
-            ])
-            .to_unf(7),
+            ])),
             vec![
                 "+1.073526e-1",
                 "+1.390415e-2",
@@ -196,19 +529,24 @@ mod tests {
     #[test]
     fn float64_truncate() {
         assert_eq!(
-            Float64Array::from(vec![0.943062649108469, 0.852143662748858]).to_unf(7),
-            vec!["+9.430626e-1", "+8.521437e-1"]
+            normalized(Float64Array::from(vec![
+                0.943062649108469,
+                0.852143662748858
+            ])),
+            vec![
+                Some("+9.430626e-1".to_string()),
+                Some("+8.521437e-1".to_string())
+            ]
         );
     }
 
     #[test]
     fn float32_raw() {
         assert_eq!(
-            Float32Array::from(vec![1.0, 2.0, 3.0, 4.0])
-                .raw(128, 7)
-                .into_iter()
-                .flatten()
-                .collect::<Vec<u8>>(),
+            encoded(
+                Float32Array::from(vec![1.0, 2.0, 3.0, 4.0]),
+                &UnfConfigBuilder::new().build()
+            ),
             vec![
                 43, 49, 46, 101, 43, 10, 0, 43, 50, 46, 101, 43, 10, 0, 43, 51, 46, 101, 43, 10, 0,
                 43, 52, 46, 101, 43, 10, 0
@@ -229,7 +567,519 @@ mod tests {
             vec![Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0, 4.0]))],
         )
         .unwrap();
-        let res = unf_from_batch(data_batch, &schema, config);
+        let res = unf_from_batch(data_batch, &schema, config).unwrap();
         assert_eq!(res.short_hash, "aWgJoh/Y7/Qo6uK9zs7ovQ==");
     }
+
+    /// UTF-32BE encoding of text, as hashed by UNF V3 and V4
+    fn utf32(text: &str) -> Vec<u8> {
+        text.chars()
+            .flat_map(|x| u32::from(x).to_be_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn v3_specification_examples() {
+        // The specification represents pi at five digits as 3.1415, truncating rather
+        // than rounding, and 300 as +3.e+2
+        let config = UnfConfigBuilder::new()
+            .version(crate::config::UnfVersion::Three)
+            .digits(5)
+            .build();
+        assert_eq!(
+            Float64Array::from(vec![std::f64::consts::PI, -std::f64::consts::PI, 300.0])
+                .to_unf(&config)
+                .unwrap(),
+            vec![
+                Some("+3.1415e+".to_string()),
+                Some("-3.1415e+".to_string()),
+                Some("+3.e+2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn float32_array_v3() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "FloatTest",
+            DataType::Float32,
+            true,
+        )]));
+        let config = UnfConfigBuilder::new()
+            .version(crate::config::UnfVersion::Three)
+            .build();
+        let values = Float32Array::from(vec![Some(1.0), None, Some(0.1)]);
+        let mut expected = utf32("+1.e+\n\0");
+        expected.extend(b"\0\0\0");
+        expected.extend(utf32("+1.e-1\n\0"));
+        assert_eq!(encoded(&values, &config), expected);
+        let data_batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(values)]).unwrap();
+        let res = unf_from_batch(data_batch, &schema, config).unwrap();
+        assert_eq!(
+            res.hash,
+            <md5::Md5 as sha2::Digest>::digest(&expected).to_vec()
+        );
+    }
+
+    #[test]
+    fn float32_raw_v4() {
+        let config = UnfConfigBuilder::new()
+            .version(crate::config::UnfVersion::Four)
+            .build();
+        assert_eq!(
+            encoded(Float32Array::from(vec![1.0]), &config),
+            vec![
+                0, 0, 0, 43, 0, 0, 0, 49, 0, 0, 0, 46, 0, 0, 0, 101, 0, 0, 0, 43, 0, 0, 0, 10, 0,
+                0, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn v4_rounds_toward_zero() {
+        let form = |version| {
+            let config = UnfConfigBuilder::new().version(version).build();
+            Float64Array::from(vec![2.0 / 3.0]).to_unf(&config).unwrap()
+        };
+        for version in [
+            crate::config::UnfVersion::Four,
+            crate::config::UnfVersion::FourOne,
+        ] {
+            assert_eq!(form(version), vec![Some("+6.666666e-1".to_string())]);
+        }
+        assert_eq!(
+            form(crate::config::UnfVersion::Five),
+            vec![Some("+6.666667e-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn string_raw_v4_1() {
+        let config = UnfConfigBuilder::new()
+            .version(crate::config::UnfVersion::FourOne)
+            .build();
+        assert_eq!(
+            encoded(StringArray::from(vec!["é"]), &config),
+            vec![195, 169, 10, 0]
+        );
+    }
+
+    #[test]
+    fn float64_missing_raw() {
+        assert_eq!(
+            encoded(
+                Float64Array::from(vec![Some(1.0), None, Some(2.0)]),
+                &UnfConfigBuilder::new().build()
+            ),
+            b"+1.e+\n\0\0\0\0+2.e+\n\0".to_vec()
+        );
+    }
+
+    #[test]
+    fn missing_values_array() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("FloatTest", DataType::Float64, true),
+            Field::new("StringTest", DataType::Utf8, true),
+        ]));
+        assert_eq!(
+            column_hashes(
+                schema,
+                vec![
+                    Arc::new(Float64Array::from(vec![Some(1.0), None, Some(2.0)])),
+                    Arc::new(StringArray::from(vec![Some("a"), None, Some("b")])),
+                ]
+            ),
+            vec!["EAGzO3LWeKsU6syiJyeyaQ==", "IEclk7pQfPzCKwmfHY9UrQ=="]
+        );
+    }
+
+    #[test]
+    fn missing_string_differs_from_empty() {
+        let raw = StringArray::from(vec![Some(""), None])
+            .raw(&UnfConfigBuilder::new().build())
+            .unwrap();
+        assert_eq!(raw, vec![b"\n\0".to_vec(), b"\0\0\0".to_vec()]);
+    }
+
+    #[test]
+    fn boolean_matches_integer() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("BooleanTest", DataType::Boolean, true),
+            Field::new("IntegerTest", DataType::Int32, true),
+        ]));
+        let hashes = column_hashes(
+            schema,
+            vec![
+                Arc::new(BooleanArray::from(vec![Some(true), None, Some(false)])),
+                Arc::new(Int32Array::from(vec![Some(1), None, Some(0)])),
+            ],
+        );
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(
+            normalized(BooleanArray::from(vec![Some(true), None, Some(false)])),
+            vec![Some("+1.e+".to_string()), None, Some("+0.e+".to_string())]
+        );
+    }
+
+    #[test]
+    fn every_integer_width() {
+        let fields = vec![
+            ("Int8", DataType::Int8),
+            ("Int16", DataType::Int16),
+            ("Int32", DataType::Int32),
+            ("Int64", DataType::Int64),
+            ("UInt8", DataType::UInt8),
+            ("UInt16", DataType::UInt16),
+            ("UInt32", DataType::UInt32),
+            ("UInt64", DataType::UInt64),
+        ];
+        let schema = Arc::new(Schema::new(
+            fields
+                .into_iter()
+                .map(|(name, data_type)| Field::new(name, data_type, true))
+                .collect(),
+        ));
+        let hashes = column_hashes(
+            schema,
+            vec![
+                Arc::new(Int8Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(Int16Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(Int32Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(Int64Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(UInt8Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(UInt16Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(UInt32Array::from(vec![Some(1), None, Some(100)])),
+                Arc::new(UInt64Array::from(vec![Some(1), None, Some(100)])),
+            ],
+        );
+        assert_eq!(hashes.len(), 8);
+        assert!(hashes.iter().all(|x| x == &hashes[0]));
+        assert_eq!(
+            normalized(Int8Array::from(vec![i8::MIN, i8::MAX])),
+            vec![Some("-1.28e+2".to_string()), Some("+1.27e+2".to_string())]
+        );
+        assert_eq!(
+            normalized(UInt8Array::from(vec![u8::MAX])),
+            vec![Some("+2.55e+2".to_string())]
+        );
+    }
+
+    #[test]
+    fn float64_special_values() {
+        assert_eq!(
+            normalized_values(Float64Array::from(vec![
+                0.0,
+                -0.0,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NAN
+            ])),
+            vec!["+0.e+", "-0.e+", "+inf", "-inf", "+nan"]
+        );
+    }
+
+    #[test]
+    fn float32_special_values() {
+        assert_eq!(
+            normalized_values(Float32Array::from(vec![
+                0.0,
+                -0.0,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::NAN
+            ])),
+            vec!["+0.e+", "-0.e+", "+inf", "-inf", "+nan"]
+        );
+    }
+
+    #[test]
+    fn float64_negative_values() {
+        assert_eq!(
+            normalized_values(Float64Array::from(vec![-1.0, -0.5, -1.23456789])),
+            vec!["-1.e+", "-5.e-1", "-1.234568e+"]
+        );
+    }
+
+    #[test]
+    fn float64_positive_exponents() {
+        assert_eq!(
+            normalized_values(Float64Array::from(vec![300.0, 12345.6789, 1.5e20])),
+            vec!["+3.e+2", "+1.234568e+4", "+1.5e+20"]
+        );
+    }
+
+    #[test]
+    fn float64_subnormal() {
+        assert_eq!(
+            normalized_values(Float64Array::from(vec![5e-324])),
+            vec!["+4.940656e-324"]
+        );
+    }
+
+    #[test]
+    fn integer_special_values() {
+        assert_eq!(
+            normalized_values(Int32Array::from(vec![0, -1, 1, 300])),
+            vec!["+0.e+", "-1.e+", "+1.e+", "+3.e+2"]
+        );
+        assert_eq!(
+            normalized_values(Int64Array::from(vec![0, -20])),
+            vec!["+0.e+", "-2.e+1"]
+        );
+        assert_eq!(
+            normalized_values(UInt64Array::from(vec![0, 10])),
+            vec!["+0.e+", "+1.e+1"]
+        );
+    }
+
+    #[test]
+    fn float32_matches_float64() {
+        let values = vec![0.1_f32, 123456.79, 3.4e38, 1.0e-40, -2.5, 16777216.0];
+        assert_eq!(
+            normalized(Float32Array::from(values.clone())),
+            normalized(Float64Array::from(
+                values.into_iter().map(f64::from).collect::<Vec<f64>>()
+            ))
+        );
+    }
+
+    #[test]
+    fn float32_significant_digits() {
+        assert_eq!(
+            normalized_values(Float32Array::from(vec![0.1, 123456.79, 3.4e38, -2.5])),
+            vec!["+1.e-1", "+1.234568e+5", "+3.4e+38", "-2.5e+"]
+        );
+    }
+
+    #[test]
+    fn float16_values() {
+        let bits: Vec<u16> = vec![0.5_f32, -1.0, 65504.0, 0.0]
+            .into_iter()
+            .map(|x| f16::from_f32(x).to_bits())
+            .collect();
+        let builder = ArrayDataBuilder::new(DataType::Float16)
+            .len(bits.len())
+            .add_buffer(Buffer::from_slice_ref(&bits));
+        // Arrow has no layout for Float16 to validate the data against
+        let data = unsafe { builder.build_unchecked() };
+        assert_eq!(
+            normalized_values(Float16Values(&data)),
+            vec!["+5.e-1", "-1.e+", "+6.5504e+4", "+0.e+"]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            normalized(Date32Array::from(vec![
+                Some(0),
+                None,
+                Some(18336),
+                Some(-9004)
+            ])),
+            vec![
+                Some("1970-01-01".to_string()),
+                None,
+                Some("2020-03-15".to_string()),
+                Some("1945-05-08".to_string())
+            ]
+        );
+        assert_eq!(
+            normalized(Date64Array::from(vec![
+                1_584_230_400_000,
+                1_542_129_070_011
+            ])),
+            vec![
+                Some("2020-03-15".to_string()),
+                Some("2018-11-13T17:11:10.011".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(
+            normalized(Time32SecondArray::from(vec![0, 45_296])),
+            vec![Some("00:00:00".to_string()), Some("12:34:56".to_string())]
+        );
+        assert_eq!(
+            normalized(Time32MillisecondArray::from(vec![45_296_500])),
+            vec![Some("12:34:56.5".to_string())]
+        );
+        assert_eq!(
+            normalized(Time64MicrosecondArray::from(vec![
+                Some(45_296_000_120),
+                None
+            ])),
+            vec![Some("12:34:56.00012".to_string()), None]
+        );
+        assert_eq!(
+            normalized(Time64NanosecondArray::from(vec![86_399_999_999_999])),
+            vec![Some("23:59:59.999999999".to_string())]
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            normalized(TimestampSecondArray::from_vec(vec![1_542_129_070], None)),
+            vec![Some("2018-11-13T17:11:10".to_string())]
+        );
+        assert_eq!(
+            normalized(TimestampMillisecondArray::from_vec(
+                vec![1_542_129_070_250, -1],
+                Some("+02:00".to_string())
+            )),
+            vec![
+                Some("2018-11-13T17:11:10.25Z".to_string()),
+                Some("1969-12-31T23:59:59.999Z".to_string())
+            ]
+        );
+        assert_eq!(
+            normalized(TimestampMicrosecondArray::from_vec(
+                vec![1_542_129_070_000_001],
+                Some("UTC".to_string())
+            )),
+            vec![Some("2018-11-13T17:11:10.000001Z".to_string())]
+        );
+        assert_eq!(
+            normalized(TimestampNanosecondArray::from_vec(vec![1], None)),
+            vec![Some("1970-01-01T00:00:00.000000001".to_string())]
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        let config = UnfConfigBuilder::new().build();
+        let out_of_range = |result: crate::error::Result<Vec<Option<String>>>| {
+            matches!(result, Err(UnfError::OutOfRange(_)))
+        };
+        assert!(out_of_range(
+            Date32Array::from(vec![i32::MAX]).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            Date32Array::from(vec![i32::MIN]).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            Date64Array::from(vec![i64::MAX]).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            Date64Array::from(vec![i64::MIN]).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            TimestampSecondArray::from_vec(vec![i64::MAX], None).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            TimestampSecondArray::from_vec(vec![i64::MIN], Some("UTC".to_string())).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            Time32SecondArray::from(vec![-1]).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            Time32SecondArray::from(vec![90_000]).to_unf(&config)
+        ));
+        assert!(out_of_range(
+            Time64NanosecondArray::from(vec![86_400_000_000_000]).to_unf(&config)
+        ));
+        assert!(matches!(
+            Date32Array::from(vec![Some(0), Some(i32::MAX)]).raw(&config),
+            Err(UnfError::OutOfRange(_))
+        ));
+        // Nanosecond timestamps span fewer years than the calendar, so all of them are in range
+        assert_eq!(
+            normalized(TimestampNanosecondArray::from_vec(
+                vec![i64::MIN, i64::MAX],
+                None
+            )),
+            vec![
+                Some("1677-09-21T00:12:43.145224192".to_string()),
+                Some("2262-04-11T23:47:16.854775807".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_representable_floats() {
+        assert_eq!(
+            normalized(decimal_array(
+                &[Some(125), Some(-50), Some(0), None, Some(1_000_000)],
+                10,
+                2
+            )),
+            normalized(Float64Array::from(vec![
+                Some(1.25),
+                Some(-0.5),
+                Some(0.0),
+                None,
+                Some(10000.0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn exact_rounding() {
+        let config = UnfConfigBuilder::new().digits(3).build();
+        // 2.675 is a tie in decimal, although its nearest double is below the tie
+        assert_eq!(
+            decimal_array(&[Some(2675)], 4, 3).to_unf(&config).unwrap(),
+            vec![Some("+2.68e+".to_string())]
+        );
+        assert_eq!(
+            normalized(decimal_array(
+                &[Some(12_345_678_901_234_567_890_123_456_789_012_345_678)],
+                38,
+                10
+            )),
+            vec![Some("+1.234568e+27".to_string())]
+        );
+    }
+
+    #[test]
+    fn bit_fields() {
+        assert_eq!(
+            normalized(BinaryArray::from_opt_vec(vec![
+                Some(&[0b0000_0101][..]),
+                Some(&[0, 0, 1, 0][..]),
+                None,
+                Some(&[0, 0][..]),
+                Some(&[][..]),
+            ])),
+            vec![
+                Some("101".to_string()),
+                Some("100000000".to_string()),
+                None,
+                Some("0".to_string()),
+                Some("0".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_widths_match() {
+        let config = UnfConfigBuilder::new().build();
+        let values: Vec<&[u8]> = vec![&[0x7f, 0x01], &[0x00, 0xff]];
+        let binary = BinaryArray::from(values.clone()).raw(&config).unwrap();
+        assert_eq!(
+            LargeBinaryArray::from(values.clone()).raw(&config).unwrap(),
+            binary
+        );
+        assert_eq!(
+            FixedSizeBinaryArray::try_from_iter(values.into_iter())
+                .unwrap()
+                .raw(&config)
+                .unwrap(),
+            binary
+        );
+    }
+
+    #[test]
+    fn bit_fields_are_not_truncated() {
+        let config = UnfConfigBuilder::new().characters(8).build();
+        let digest = vec![0xff_u8; 32];
+        let raw = FixedSizeBinaryArray::try_from_iter(vec![digest].into_iter())
+            .unwrap()
+            .raw(&config)
+            .unwrap();
+        assert_eq!(raw[0].len(), 256 + 2);
+    }
 }
@@ -1,11 +1,787 @@
-use std::fs::File;
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    str::FromStr,
+    sync::Arc,
+};
 
-use arrow::csv;
+use arrow::{
+    array::{
+        Array, ArrayRef, Date32Array, StringArray, Time64NanosecondArray, TimestampMicrosecondArray,
+    },
+    csv,
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::{ArrowError, Result as ArrowResult},
+    ipc, json,
+    record_batch::RecordBatch,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+#[cfg(feature = "parquet")]
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReader, ArrowReader, ParquetFileArrowReader},
+    file::reader::{FileReader, SerializedFileReader},
+};
 
-pub fn read_csv_data(file_path: String, lines_for_type_inference: usize) -> csv::Reader<File> {
-    let file = File::open(file_path).unwrap();
+use crate::error::{Result as UnfResult, UnfError};
+pub use crate::spss::{read_spss_data, SpssReader};
+pub use crate::stata::{read_stata_data, StataReader};
+
+pub fn read_csv_data(
+    file_path: String,
+    lines_for_type_inference: usize,
+) -> UnfResult<csv::Reader<File>> {
+    let file = File::open(file_path)?;
     let builder = csv::ReaderBuilder::new()
         .has_header(true)
         .infer_schema(Some(lines_for_type_inference));
-    builder.build(file).unwrap()
+    Ok(builder.build(file)?)
+}
+
+/// Read an Arrow IPC file, which is also the format of Feather v2 files
+///
+/// Batches are read one at a time. Files with buffers compressed with LZ4 or ZSTD, which pyarrow
+/// writes to Feather files by default, are rejected because the Arrow reader would misread them.
+pub fn read_ipc_file(file_path: String) -> UnfResult<ipc::reader::FileReader<BufReader<File>>> {
+    let mut file = BufReader::new(File::open(file_path)?);
+    check_ipc_file(&mut file)?;
+    file.rewind()?;
+    Ok(ipc::reader::FileReader::try_new(file)?)
+}
+
+/// Read an Arrow IPC stream, such as one piped through standard input
+///
+/// Like files, streams with compressed buffers are rejected, though as the batch is read.
+pub fn read_ipc_stream<R: Read>(
+    reader: R,
+) -> UnfResult<ipc::reader::StreamReader<BufReader<UncompressedStream<R>>>> {
+    Ok(ipc::reader::StreamReader::try_new(BufReader::new(
+        UncompressedStream::new(reader),
+    ))?)
+}
+
+const ARROW_MAGIC: [u8; 6] = *b"ARROW1";
+const CONTINUATION_MARKER: [u8; 4] = [0xff; 4];
+
+/// Check that no dictionary or record batch listed in the footer of an IPC file is compressed
+fn check_ipc_file<R: Read + Seek>(reader: &mut R) -> UnfResult<()> {
+    let mut magic = [0; 6];
+    reader.read_exact(&mut magic)?;
+    if magic != ARROW_MAGIC {
+        // Not an IPC file, which the Arrow reader reports
+        return Ok(());
+    }
+    let mut footer_size = [0; 4];
+    reader.seek(SeekFrom::End(-10))?;
+    reader.read_exact(&mut footer_size)?;
+    let footer_len = i32::from_le_bytes(footer_size);
+    let mut footer_data = vec![0; footer_len as usize];
+    reader.seek(SeekFrom::End(-10 - footer_len as i64))?;
+    reader.read_exact(&mut footer_data)?;
+    let footer = ipc::root_as_footer(&footer_data)
+        .map_err(|err| ArrowError::IoError(format!("Unable to get root as footer: {:?}", err)))?;
+    let blocks = footer.dictionaries().into_iter().flatten();
+    for block in blocks.chain(footer.recordBatches().into_iter().flatten()) {
+        reader.seek(SeekFrom::Start(block.offset() as u64))?;
+        let mut message_size = [0; 4];
+        reader.read_exact(&mut message_size)?;
+        if message_size == CONTINUATION_MARKER {
+            reader.read_exact(&mut message_size)?;
+        }
+        let mut message = vec![0; i32::from_le_bytes(message_size) as usize];
+        reader.read_exact(&mut message)?;
+        check_uncompressed(&message)?;
+    }
+    Ok(())
+}
+
+/// Reject an IPC message whose record batch, or dictionary batch, has compressed buffers
+///
+/// Returns the length of the message body that follows.
+fn check_uncompressed(message: &[u8]) -> UnfResult<usize> {
+    let message = ipc::root_as_message(message)
+        .map_err(|err| ArrowError::IoError(format!("Unable to get root as message: {:?}", err)))?;
+    let batch = match message.header_type() {
+        ipc::MessageHeader::RecordBatch => message.header_as_record_batch(),
+        ipc::MessageHeader::DictionaryBatch => message
+            .header_as_dictionary_batch()
+            .and_then(|dictionary| dictionary.data()),
+        _ => None,
+    };
+    match batch.and_then(|batch| batch.compression()) {
+        Some(compression) => Err(UnfError::UnsupportedCompression(format!(
+            "{:?}",
+            compression.codec()
+        ))),
+        None => Ok(message.bodyLength().max(0) as usize),
+    }
+}
+
+/// An IPC stream that fails to read any message with compressed buffers
+///
+/// Messages are passed through whole, so one message body is held in memory at a time, as the
+/// Arrow stream reader does.
+pub struct UncompressedStream<R> {
+    reader: R,
+    message: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> UncompressedStream<R> {
+    fn new(reader: R) -> Self {
+        UncompressedStream {
+            reader,
+            message: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Read up to `len` bytes onto the end of the current message, returning how many were read
+    fn take(&mut self, len: usize) -> io::Result<usize> {
+        let start = self.message.len();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut self.message)?;
+        Ok(self.message.len() - start)
+    }
+
+    /// Read the next message, leaving anything truncated for the Arrow reader to report
+    fn next_message(&mut self) -> io::Result<()> {
+        self.message.clear();
+        self.position = 0;
+        if self.take(4)? < 4 {
+            return Ok(());
+        }
+        if self.message[..4] == CONTINUATION_MARKER {
+            self.message.clear();
+            if self.take(4)? < 4 {
+                return Ok(());
+            }
+            self.message.splice(0..0, CONTINUATION_MARKER);
+        }
+        let size: [u8; 4] = self.message[self.message.len() - 4..].try_into().unwrap();
+        let len = i32::from_le_bytes(size).max(0) as usize;
+        let start = self.message.len();
+        if self.take(len)? < len {
+            return Ok(());
+        }
+        if len == 0 {
+            // The end of the stream
+            return Ok(());
+        }
+        let body_length = check_uncompressed(&self.message[start..])
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.take(body_length)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for UncompressedStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.message.len() {
+            self.next_message()?;
+        }
+        let len = buf.len().min(self.message.len() - self.position);
+        buf[..len].copy_from_slice(&self.message[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Read a Parquet file one row group at a time, in batches of `batch_size` rows
+///
+/// When `columns` is given only the named top-level columns are read, so memory is bounded
+/// by the selected columns of a single batch rather than by the whole file.
+#[cfg(feature = "parquet")]
+pub fn read_parquet_data(
+    file_path: String,
+    columns: Option<&[String]>,
+    batch_size: usize,
+) -> UnfResult<ParquetRecordBatchReader> {
+    let file_reader = Arc::new(SerializedFileReader::new(File::open(file_path)?)?);
+    let schema = file_reader.metadata().file_metadata().schema_descr_ptr();
+    let mut arrow_reader = ParquetFileArrowReader::new(file_reader);
+    Ok(match columns {
+        Some(columns) => {
+            if let Some(name) = columns.iter().find(|name| {
+                !schema
+                    .columns()
+                    .iter()
+                    .any(|column| &column.path().parts()[0] == *name)
+            }) {
+                return Err(UnfError::SchemaMismatch(format!(
+                    "Column {} is not in the file",
+                    name
+                )));
+            }
+            // Projection selects leaf columns, so every leaf of a selected column is read
+            let leaves = schema
+                .columns()
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| columns.contains(&column.path().parts()[0]))
+                .map(|(index, _)| index);
+            arrow_reader.get_record_reader_by_columns(leaves, batch_size)?
+        }
+        None => arrow_reader.get_record_reader(batch_size)?,
+    })
+}
+
+/// Read a newline-delimited JSON file, inferring its schema from every line
+///
+/// The whole file is inferred so that no field goes unread. Fields that are absent from a
+/// line are read as missing values, and fields that are only ever null become `Null`
+/// columns of missing values.
+pub fn read_ndjson_data(file_path: String) -> UnfResult<json::Reader<File>> {
+    let mut reader = BufReader::new(File::open(file_path)?);
+    let mut keys = Vec::new();
+    let values = json::reader::ValueIter::new(&mut reader, None).inspect(|value| {
+        if let Ok(serde_json::Value::Object(map)) = value {
+            for key in map.keys() {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+    });
+    let inferred = json::reader::infer_json_schema_from_iterator(values)?;
+    let mut fields = inferred.fields().clone();
+    for key in keys {
+        if inferred.field_with_name(&key).is_err() {
+            fields.push(Field::new(&key, DataType::Null, true));
+        }
+    }
+    reader.rewind()?;
+    Ok(json::Reader::from_buf_reader(
+        reader,
+        Arc::new(Schema::new(fields)),
+        1024,
+        None,
+    ))
+}
+
+/// Temporal interpretation of a CSV column, which type inference would otherwise leave as text
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TemporalType {
+    /// `YYYY-MM-DD`, read as a Date32
+    Date,
+    /// `hh:mm:ss` with optional fractional seconds, read as a Time64
+    Time,
+    /// `YYYY-MM-DDThh:mm:ss` without a time zone, read as a Timestamp without a time zone
+    DateTime,
+    /// RFC 3339 date-times with an offset or `Z`, read as a UTC Timestamp
+    ZonedDateTime,
+}
+
+impl TemporalType {
+    fn data_type(&self) -> DataType {
+        match self {
+            TemporalType::Date => DataType::Date32,
+            TemporalType::Time => DataType::Time64(TimeUnit::Nanosecond),
+            TemporalType::DateTime => DataType::Timestamp(TimeUnit::Microsecond, None),
+            TemporalType::ZonedDateTime => {
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".to_string()))
+            }
+        }
+    }
+
+    fn parse_column(&self, column: &StringArray) -> ArrowResult<ArrayRef> {
+        let values = (0..column.len()).map(|index| {
+            if column.is_null(index) || column.value(index).is_empty() {
+                Ok(None)
+            } else {
+                self.parse_value(column.value(index)).map(Some)
+            }
+        });
+        Ok(match self {
+            TemporalType::Date => Arc::new(Date32Array::from(
+                values
+                    .map(|x| x.map(|x| x.map(|x| x as i32)))
+                    .collect::<ArrowResult<Vec<_>>>()?,
+            )),
+            TemporalType::Time => Arc::new(Time64NanosecondArray::from(
+                values.collect::<ArrowResult<Vec<_>>>()?,
+            )),
+            TemporalType::DateTime => Arc::new(TimestampMicrosecondArray::from_opt_vec(
+                values.collect::<ArrowResult<Vec<_>>>()?,
+                None,
+            )),
+            TemporalType::ZonedDateTime => Arc::new(TimestampMicrosecondArray::from_opt_vec(
+                values.collect::<ArrowResult<Vec<_>>>()?,
+                Some("UTC".to_string()),
+            )),
+        })
+    }
+
+    /// Parse a value into the native representation of the column's Arrow type
+    fn parse_value(&self, value: &str) -> ArrowResult<i64> {
+        let parse_error =
+            |_| ArrowError::ParseError(format!("Error parsing '{}' as {:?}", value, self));
+        match self {
+            TemporalType::Date => {
+                let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(parse_error)?;
+                Ok(date.and_hms(0, 0, 0).timestamp() / 86_400)
+            }
+            TemporalType::Time => {
+                let time = value.parse::<NaiveTime>().map_err(parse_error)?;
+                Ok(i64::from(time.num_seconds_from_midnight()) * 1_000_000_000
+                    + i64::from(time.nanosecond()))
+            }
+            TemporalType::DateTime => {
+                let datetime = value.parse::<NaiveDateTime>().map_err(parse_error)?;
+                Ok(
+                    datetime.timestamp() * 1_000_000
+                        + i64::from(datetime.timestamp_subsec_micros()),
+                )
+            }
+            TemporalType::ZonedDateTime => {
+                let datetime = DateTime::parse_from_rfc3339(value).map_err(parse_error)?;
+                Ok(
+                    datetime.timestamp() * 1_000_000
+                        + i64::from(datetime.timestamp_subsec_micros()),
+                )
+            }
+        }
+    }
+}
+
+impl FromStr for TemporalType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(TemporalType::Date),
+            "time" => Ok(TemporalType::Time),
+            "datetime" => Ok(TemporalType::DateTime),
+            "zoned-datetime" => Ok(TemporalType::ZonedDateTime),
+            _ => Err(format!("Unknown temporal type {}", s)),
+        }
+    }
+}
+
+/// CSV Reader that converts named text columns into temporal columns
+pub struct TemporalCsvReader<R: Read> {
+    reader: csv::Reader<R>,
+    schema: SchemaRef,
+    temporal_columns: Vec<(usize, TemporalType)>,
+}
+
+impl<R: Read> TemporalCsvReader<R> {
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl<R: Read> Iterator for TemporalCsvReader<R> {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.reader.next()? {
+            Ok(batch) => batch,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut columns = batch.columns().to_vec();
+        for (index, temporal_type) in &self.temporal_columns {
+            let text = columns[*index]
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .expect("Temporal columns are read as text");
+            columns[*index] = match temporal_type.parse_column(text) {
+                Ok(column) => column,
+                Err(e) => return Some(Err(e)),
+            };
+        }
+        Some(RecordBatch::try_new(self.schema.clone(), columns))
+    }
+}
+
+/// Read a CSV file, treating the named columns as temporal rather than inferring their types
+pub fn read_csv_temporal(
+    file_path: String,
+    lines_for_type_inference: usize,
+    temporal_columns: &[(String, TemporalType)],
+) -> UnfResult<TemporalCsvReader<File>> {
+    let file = File::open(file_path)?;
+    temporal_csv_reader(file, lines_for_type_inference, temporal_columns)
+}
+
+fn temporal_csv_reader<R: Read + Seek>(
+    mut reader: R,
+    lines_for_type_inference: usize,
+    temporal_columns: &[(String, TemporalType)],
+) -> UnfResult<TemporalCsvReader<R>> {
+    let (inferred_schema, _) =
+        csv::reader::infer_file_schema(&mut reader, b',', Some(lines_for_type_inference), true)?;
+    let mut text_fields = Vec::with_capacity(inferred_schema.fields().len());
+    let mut temporal_fields = Vec::with_capacity(inferred_schema.fields().len());
+    let mut temporal_indices = Vec::with_capacity(temporal_columns.len());
+    for (index, field) in inferred_schema.fields().iter().enumerate() {
+        match temporal_columns
+            .iter()
+            .find(|(name, _)| name == field.name())
+        {
+            Some((_, temporal_type)) => {
+                text_fields.push(Field::new(field.name(), DataType::Utf8, true));
+                temporal_fields.push(Field::new(field.name(), temporal_type.data_type(), true));
+                temporal_indices.push((index, *temporal_type));
+            }
+            None => {
+                text_fields.push(field.clone());
+                temporal_fields.push(field.clone());
+            }
+        }
+    }
+    if let Some((name, _)) = temporal_columns
+        .iter()
+        .find(|(name, _)| inferred_schema.field_with_name(name).is_err())
+    {
+        return Err(UnfError::SchemaMismatch(format!(
+            "Temporal column {} is not in the file",
+            name
+        )));
+    }
+    let reader = csv::ReaderBuilder::new()
+        .has_header(true)
+        .with_schema(Arc::new(Schema::new(text_fields)))
+        .build(reader)?;
+    Ok(TemporalCsvReader {
+        reader,
+        schema: Arc::new(Schema::new(temporal_fields)),
+        temporal_columns: temporal_indices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temporal_columns() {
+        let file_path = "data/TemporalData.csv";
+        let reader = read_csv_temporal(
+            file_path.to_string(),
+            100,
+            &[
+                ("interview_date".to_string(), TemporalType::Date),
+                ("start_time".to_string(), TemporalType::Time),
+                ("recorded".to_string(), TemporalType::DateTime),
+                ("submitted".to_string(), TemporalType::ZonedDateTime),
+            ],
+        )
+        .unwrap();
+        let schema = reader.schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(1).data_type(), &DataType::Date32);
+        assert_eq!(
+            schema.field(4).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".to_string()))
+        );
+        let batches = reader.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        let submitted = batches[0]
+            .column(4)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        // 2021-03-04T10:15:00+02:00 is stored as the UTC instant
+        assert_eq!(submitted.value(0), 1_614_845_700_000_000);
+        assert!(submitted.is_null(1));
+        let start_time = batches[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<Time64NanosecondArray>()
+            .unwrap();
+        assert_eq!(start_time.value(1), 50_400_250_000_000);
+    }
+
+    #[test]
+    fn missing_temporal_column() {
+        let file = File::open("data/TemporalData.csv").unwrap();
+        assert!(
+            temporal_csv_reader(file, 100, &[("absent".to_string(), TemporalType::Date)]).is_err()
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        assert!(matches!(
+            read_csv_data("data/Absent.csv".to_string(), 100),
+            Err(UnfError::Io(_))
+        ));
+    }
+
+    #[cfg(feature = "parquet")]
+    fn write_parquet(file_name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(file_name);
+        let csv = read_csv_data("data/ExampleData.csv".to_string(), 100).unwrap();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            File::create(&path).unwrap(),
+            csv.schema(),
+            Some(
+                parquet::file::properties::WriterProperties::builder()
+                    .set_max_row_group_size(1024)
+                    .build(),
+            ),
+        )
+        .unwrap();
+        for batch in csv {
+            writer.write(&batch.unwrap()).unwrap();
+        }
+        writer.close().unwrap();
+        path
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn parquet_matches_csv() {
+        use crate::{calculate_unf, config::UnfConfigBuilder, hash_builder::UnfHashBuilder};
+        use arrow::record_batch::RecordBatchReader;
+
+        let path = write_parquet("unf_parquet_matches_csv.parquet");
+        let config = UnfConfigBuilder::new().build();
+        let parquet = read_parquet_data(path.to_string_lossy().to_string(), None, 500).unwrap();
+        let unf_hash = UnfHashBuilder::new(parquet.schema(), config);
+        let res = calculate_unf(unf_hash, parquet).unwrap();
+        assert_eq!(res.short_hash, "Isf0CgUVrEZzLZdf5G46TA==");
+
+        let parquet = read_parquet_data(
+            path.to_string_lossy().to_string(),
+            Some(&["b".to_string()]),
+            500,
+        )
+        .unwrap();
+        assert_eq!(parquet.schema().fields().len(), 1);
+        assert_eq!(parquet.schema().field(0).name(), "b");
+        let batches = parquet.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        assert!(batches.iter().all(|x| x.num_rows() <= 500));
+        assert_eq!(batches.iter().map(|x| x.num_rows()).sum::<usize>(), 5000);
+
+        assert!(matches!(
+            read_parquet_data(
+                path.to_string_lossy().to_string(),
+                Some(&["absent".to_string()]),
+                500
+            ),
+            Err(UnfError::SchemaMismatch(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ipc_matches_csv() {
+        use crate::{calculate_unf, config::UnfConfigBuilder, hash_builder::UnfHashBuilder};
+
+        let csv = read_csv_data("data/ExampleData.csv".to_string(), 100).unwrap();
+        let schema = csv.schema();
+        let batches = csv.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        let path = std::env::temp_dir().join("unf_ipc_matches_csv.arrow");
+        let mut file_writer =
+            ipc::writer::FileWriter::try_new(File::create(&path).unwrap(), &schema).unwrap();
+        let mut stream = Vec::new();
+        {
+            let mut stream_writer =
+                ipc::writer::StreamWriter::try_new(&mut stream, &schema).unwrap();
+            for batch in &batches {
+                file_writer.write(batch).unwrap();
+                stream_writer.write(batch).unwrap();
+            }
+            file_writer.finish().unwrap();
+            stream_writer.finish().unwrap();
+        }
+
+        let config = UnfConfigBuilder::new().build();
+        let file = read_ipc_file(path.to_string_lossy().to_string()).unwrap();
+        let unf_hash = UnfHashBuilder::new(file.schema(), config);
+        let res = calculate_unf(unf_hash, file).unwrap();
+        assert_eq!(res.short_hash, "Isf0CgUVrEZzLZdf5G46TA==");
+
+        let stream = read_ipc_stream(std::io::Cursor::new(stream)).unwrap();
+        let unf_hash = UnfHashBuilder::new(stream.schema(), config);
+        let res = calculate_unf(unf_hash, stream).unwrap();
+        assert_eq!(res.short_hash, "Isf0CgUVrEZzLZdf5G46TA==");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ndjson_schema_inference() {
+        let reader = read_ndjson_data("data/Events.ndjson".to_string()).unwrap();
+        let schema = reader.schema();
+        assert_eq!(
+            schema.field_with_name("id").unwrap().data_type(),
+            &DataType::Int64
+        );
+        assert_eq!(
+            schema.field_with_name("duration").unwrap().data_type(),
+            &DataType::Float64
+        );
+        let batches = reader.collect::<ArrowResult<Vec<RecordBatch>>>().unwrap();
+        let duration = batches[0]
+            .column(schema.index_of("duration").unwrap())
+            .clone();
+        assert_eq!(duration.null_count(), 2);
+    }
+
+    #[test]
+    fn ndjson_matches_csv() {
+        use crate::{calculate_unf, config::UnfConfigBuilder, hash_builder::UnfHashBuilder};
+
+        let csv = std::fs::read_to_string("data/ExampleData.csv").unwrap();
+        let ndjson = csv
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let (a, b) = line.split_once(',').unwrap();
+                format!("{{\"a\": {}, \"b\": {}}}\n", a, b)
+            })
+            .collect::<String>();
+        let path = std::env::temp_dir().join("unf_ndjson_matches_csv.ndjson");
+        std::fs::write(&path, ndjson).unwrap();
+        let config = UnfConfigBuilder::new().build();
+        let reader = read_ndjson_data(path.to_string_lossy().to_string()).unwrap();
+        let unf_hash = UnfHashBuilder::new(reader.schema(), config);
+        let res = calculate_unf(unf_hash, reader).unwrap();
+        assert_eq!(res.short_hash, "Isf0CgUVrEZzLZdf5G46TA==");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ndjson_reads_every_field() {
+        use crate::{
+            calculate_dataset_unf, config::UnfConfigBuilder, hash_builder::UnfHashBuilder,
+        };
+
+        let unf = |name: &str, ndjson: &str| {
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, ndjson).unwrap();
+            let reader = read_ndjson_data(path.to_string_lossy().to_string()).unwrap();
+            let schema = reader.schema();
+            let unf_hash = UnfHashBuilder::new(reader.schema(), UnfConfigBuilder::new().build());
+            let res = calculate_dataset_unf(unf_hash, reader).unwrap();
+            std::fs::remove_file(path).unwrap();
+            (schema, res.unf.short_hash)
+        };
+
+        // A field first seen after many lines is still read
+        let late = format!("{}{{\"a\": 0, \"b\": \"x\"}}\n", "{\"a\": 1}\n".repeat(200));
+        let (schema, _) = unf("unf_ndjson_late_field.ndjson", &late);
+        assert_eq!(
+            schema.field_with_name("b").unwrap().data_type(),
+            &DataType::Utf8
+        );
+
+        // A field that is only ever null is hashed as a column of missing values
+        let (schema, with_null) = unf(
+            "unf_ndjson_null_field.ndjson",
+            "{\"a\": 1, \"b\": null}\n{\"a\": 2, \"b\": null}\n",
+        );
+        assert_eq!(
+            schema.field_with_name("b").unwrap().data_type(),
+            &DataType::Null
+        );
+        let (_, without_null) = unf("unf_ndjson_no_field.ndjson", "{\"a\": 1}\n{\"a\": 2}\n");
+        assert_ne!(with_null, without_null);
+    }
+
+    /// An IPC file and stream of a batch whose metadata declares LZ4 compressed buffers, as
+    /// pyarrow writes by default, which the Arrow writer here cannot do itself
+    fn compressed_ipc(batch: &RecordBatch) -> (Vec<u8>, Vec<u8>) {
+        use ipc::writer::{write_message, DictionaryTracker, IpcDataGenerator, IpcWriteOptions};
+
+        let options = IpcWriteOptions::default();
+        let generator = IpcDataGenerator::default();
+        let (_, encoded) = generator
+            .encoded_batch(batch, &mut DictionaryTracker::new(false), &options)
+            .unwrap();
+        let message = ipc::root_as_message(&encoded.ipc_message).unwrap();
+        let header = message.header_as_record_batch().unwrap();
+        let mut fbb = flatbuffers::FlatBufferBuilder::new();
+        let nodes = fbb.create_vector(header.nodes().unwrap());
+        let buffers = fbb.create_vector(header.buffers().unwrap());
+        let compression = ipc::BodyCompression::create(
+            &mut fbb,
+            &ipc::BodyCompressionArgs {
+                codec: ipc::CompressionType::LZ4_FRAME,
+                method: ipc::BodyCompressionMethod::BUFFER,
+            },
+        );
+        let header = ipc::RecordBatch::create(
+            &mut fbb,
+            &ipc::RecordBatchArgs {
+                length: header.length(),
+                nodes: Some(nodes),
+                buffers: Some(buffers),
+                compression: Some(compression),
+            },
+        );
+        let root = ipc::Message::create(
+            &mut fbb,
+            &ipc::MessageArgs {
+                version: message.version(),
+                header_type: ipc::MessageHeader::RecordBatch,
+                header: Some(header.as_union_value()),
+                bodyLength: message.bodyLength(),
+                custom_metadata: None,
+            },
+        );
+        fbb.finish(root, None);
+        let compressed = || ipc::writer::EncodedData {
+            ipc_message: fbb.finished_data().to_vec(),
+            arrow_data: encoded.arrow_data.clone(),
+        };
+        let schema = || generator.schema_to_bytes(&batch.schema(), &options);
+        let end = [CONTINUATION_MARKER, [0; 4]].concat();
+
+        let mut stream = Vec::new();
+        write_message(&mut stream, schema(), &options).unwrap();
+        write_message(&mut stream, compressed(), &options).unwrap();
+        stream.extend(&end);
+
+        let mut file = [&ARROW_MAGIC[..], &[0; 2]].concat();
+        write_message(&mut file, schema(), &options).unwrap();
+        let offset = file.len() as i64;
+        let (meta, body) = write_message(&mut file, compressed(), &options).unwrap();
+        file.extend(&end);
+        let mut fbb = flatbuffers::FlatBufferBuilder::new();
+        let dictionaries = fbb.create_vector::<ipc::Block>(&[]);
+        let blocks = fbb.create_vector(&[ipc::Block::new(offset, meta as i32, body as i64)]);
+        let schema = ipc::convert::schema_to_fb_offset(&mut fbb, &batch.schema());
+        let footer = ipc::Footer::create(
+            &mut fbb,
+            &ipc::FooterArgs {
+                version: ipc::MetadataVersion::V5,
+                schema: Some(schema),
+                dictionaries: Some(dictionaries),
+                recordBatches: Some(blocks),
+                custom_metadata: None,
+            },
+        );
+        fbb.finish(footer, None);
+        file.extend(fbb.finished_data());
+        file.extend(&(fbb.finished_data().len() as i32).to_le_bytes());
+        file.extend(&ARROW_MAGIC);
+        (file, stream)
+    }
+
+    #[test]
+    fn ipc_rejects_compression() {
+        let batch = read_csv_data("data/ExampleData.csv".to_string(), 100)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let (file, stream) = compressed_ipc(&batch);
+
+        let path = std::env::temp_dir().join("unf_ipc_rejects_compression.arrow");
+        std::fs::write(&path, file).unwrap();
+        let res = read_ipc_file(path.to_string_lossy().to_string());
+        std::fs::remove_file(path).unwrap();
+        assert!(
+            matches!(res, Err(UnfError::UnsupportedCompression(codec)) if codec == "LZ4_FRAME")
+        );
+
+        let mut stream = read_ipc_stream(std::io::Cursor::new(stream)).unwrap();
+        let err = stream.next().unwrap().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unsupported IPC compression LZ4_FRAME"));
+    }
 }